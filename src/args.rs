@@ -1,10 +1,45 @@
+use ravenlib::themes::{Part, Selection};
 #[derive(StructOpt, Debug)]
 #[structopt(name = "raven")]
+pub struct RavenArgs {
+    /// Use a different raven directory instead of $RAVEN_HOME or ~/.config/raven
+    #[structopt(long = "root")]
+    pub root: Option<String>,
+    #[structopt(subcommand)]
+    pub command: Raven,
+}
+#[derive(StructOpt, Debug)]
 pub enum Raven {
     #[structopt(name = "load", about = "Load a complete theme")]
-    Load { theme: String },
+    Load {
+        theme: String,
+        /// Print the files that would be written and the commands that would run, without
+        /// changing anything
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        /// Load one of the theme's variants, such as light or dark
+        #[structopt(long = "variant")]
+        variant: Option<String>,
+        #[structopt(flatten)]
+        select: Select,
+    },
     #[structopt(name = "new", about = "Create a new theme")]
-    New { name: String },
+    New {
+        name: String,
+        /// Build on an existing theme, inheriting the options this one doesn't set
+        #[structopt(long = "extends")]
+        extends: Option<String>,
+    },
+    #[structopt(
+        name = "compose",
+        about = "Create a new theme from options of existing themes"
+    )]
+    Compose {
+        name: String,
+        /// Options to take from a theme, as theme:option,option. Can be repeated
+        #[structopt(long = "from", raw(required = "true", number_of_values = "1"))]
+        from: Vec<Part>,
+    },
     #[structopt(
         name = "modify",
         about = "Open the currently edited themes's option in $EDITOR"
@@ -22,8 +57,26 @@ pub enum Raven {
         about = "Print info about the theme being currently edited"
     )]
     Info {},
+    #[structopt(
+        name = "toggle-variant",
+        about = "Load the last loaded theme with its next variant"
+    )]
+    ToggleVariant {},
+    #[structopt(
+        name = "capture",
+        about = "Create a new theme from the configs currently in use"
+    )]
+    Capture {
+        name: String,
+        /// Don't ask for confirmation
+        #[structopt(short = "y", long = "yes")]
+        yes: bool,
+    },
     #[structopt(name = "refresh", about = "Load last loaded theme")]
-    Refresh {},
+    Refresh {
+        #[structopt(flatten)]
+        select: Select,
+    },
     #[structopt(name = "install", about = "Install a theme from ThemeHub repo")]
     Install {
         name: String,
@@ -32,22 +85,88 @@ pub enum Raven {
         force: bool,
     },
     #[structopt(name = "add", about = "Add option to current theme")]
-    Add { option: String, name: String },
+    Add {
+        option: String,
+        name: String,
+        /// Add the file to a variant of the theme instead, such as light or dark
+        #[structopt(long = "variant")]
+        variant: Option<String>,
+    },
     #[structopt(name = "rm", about = "Remove an option from edited theme")]
     Rm { name: String },
+    #[structopt(
+        name = "enable",
+        about = "Load a disabled option of the edited theme again"
+    )]
+    Enable { option: String },
+    #[structopt(
+        name = "disable",
+        about = "Stop loading an option of the edited theme, without removing it"
+    )]
+    Disable { option: String },
+    #[structopt(
+        name = "tag",
+        about = "Tag the edited theme, for picking it into playlists"
+    )]
+    Tag { tag: String },
+    #[structopt(name = "untag", about = "Remove a tag from the edited theme")]
+    Untag { tag: String },
+    #[structopt(
+        name = "template",
+        about = "Render an option of the edited theme as a template when loading"
+    )]
+    Template { option: String },
+    #[structopt(
+        name = "untemplate",
+        about = "Use an option file of the edited theme as it is, without rendering it"
+    )]
+    Untemplate { option: String },
     #[structopt(name = "key", about = "Add a key-value option")]
     Key {
         key: String,
-        value: String
+        value: String,
+        /// Set the value in a variant of the theme instead, such as light or dark
+        #[structopt(long = "variant")]
+        variant: Option<String>,
     },
     #[structopt(name = "edit", about = "Edit theme")]
     Edit { name: String },
     #[structopt(name = "menu", about = "Show theme menu")]
-    Menu {},
+    Menu {
+        #[structopt(flatten)]
+        select: Select,
+    },
+    #[structopt(
+        name = "restore-original",
+        about = "Put back the config files raven overwrote since it was first used"
+    )]
+    RestoreOriginal {},
     #[structopt(name = "manage", about = "Manage online themes")]
     ManageO(Manage),
     #[structopt(name = "cycle", about = "Control cycle daemon")]
     CycleD(Cycle),
+    #[structopt(name = "palette", about = "Manage the edited theme's color palette")]
+    PaletteO(PaletteAction),
+    #[structopt(
+        name = "rules",
+        about = "Check the rules for switching themes automatically"
+    )]
+    RulesO(RulesAction),
+}
+/// Flags for loading only some of a theme's options
+#[derive(StructOpt, Debug)]
+pub struct Select {
+    /// Only load these options, separated by commas
+    #[structopt(long = "only", raw(use_delimiter = "true"))]
+    pub only: Vec<String>,
+    /// Don't load these options, separated by commas
+    #[structopt(long = "skip", raw(use_delimiter = "true"))]
+    pub skip: Vec<String>,
+}
+impl Select {
+    pub fn selection(self) -> Selection {
+        Selection::new(self.only, self.skip)
+    }
 }
 #[derive(StructOpt, Debug)]
 pub enum Manage {
@@ -77,10 +196,51 @@ pub enum Manage {
 }
 #[derive(StructOpt, Debug)]
 pub enum Cycle {
-    #[structopt(name = "start", about = "Start the daemon")]
-    Start {},
+    #[structopt(
+        name = "start",
+        about = "Start the daemon, cycling through a playlist or all themes"
+    )]
+    Start { playlist: Option<String> },
     #[structopt(name = "check", about = "Check if daemon is running")]
     Check {},
     #[structopt(name = "stop", about = "Stop the daemon")]
     Stop {},
+    #[structopt(name = "next", about = "Switch to the next theme now")]
+    Next {},
+    #[structopt(name = "prev", about = "Switch back to the previous theme now")]
+    Prev {},
+    #[structopt(name = "pause", about = "Stop switching themes until resumed")]
+    Pause {},
+    #[structopt(name = "resume", about = "Continue switching themes")]
+    Resume {},
+    #[structopt(
+        name = "status",
+        about = "Show the current theme and the time until the next switch"
+    )]
+    Status {},
+    #[structopt(
+        name = "set-interval",
+        about = "Change the number of seconds between themes"
+    )]
+    SetInterval { seconds: u64 },
+}
+#[derive(StructOpt, Debug)]
+pub enum RulesAction {
+    #[structopt(name = "test", about = "Show which rule matches right now, and why")]
+    Test {},
+}
+#[derive(StructOpt, Debug)]
+pub enum PaletteAction {
+    #[structopt(
+        name = "set",
+        about = "Set a color: background, foreground, cursor, color0-color15 or an accent name"
+    )]
+    Set { key: String, value: String },
+    #[structopt(name = "show", about = "Show the palette")]
+    Show {},
+    #[structopt(
+        name = "import",
+        about = "Import colors from a pywal colors.json or an Xresources file"
+    )]
+    Import { file: String },
 }
@@ -0,0 +1,93 @@
+use error::RavenError;
+use paths::RavenPaths;
+use serde_json;
+use std::{fs, io, os::unix::fs::symlink, path::Path};
+/// What a target file was before raven first wrote to it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Original {
+    /// The target path raven writes to
+    pub target: String,
+    /// Copy of the original file within the backup directory, if it was a regular file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Where the original pointed to, if it was a symlink
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+impl Original {
+    /// Whether the target didn't exist before raven created it
+    pub fn created(&self) -> bool {
+        self.file.is_none() && self.link.is_none()
+    }
+}
+/// Reads the list of saved originals
+pub fn originals(paths: &RavenPaths) -> Result<Vec<Original>, RavenError> {
+    match fs::read_to_string(paths.backup_file("originals.json")) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+fn up_originals(paths: &RavenPaths, originals: &[Original]) -> Result<(), RavenError> {
+    fs::create_dir_all(paths.backup())?;
+    let tmp = paths.backup_file("~originals.json");
+    fs::write(&tmp, serde_json::to_string_pretty(originals)?)?;
+    fs::rename(tmp, paths.backup_file("originals.json"))?;
+    Ok(())
+}
+/// Saves a target file into the backup directory, unless it was already saved. Must be called
+/// before raven writes to or removes any file outside its own directories
+pub fn save_original<N>(paths: &RavenPaths, target: N) -> Result<(), RavenError>
+where
+    N: Into<String>,
+{
+    let target = target.into();
+    let mut saved = originals(paths)?;
+    if saved.iter().any(|x| x.target == target) {
+        return Ok(());
+    }
+    let mut original = Original {
+        target: target.clone(),
+        file: None,
+        link: None,
+    };
+    match fs::symlink_metadata(&target) {
+        Ok(ref meta) if meta.file_type().is_symlink() => {
+            original.link = Some(fs::read_link(&target)?.to_string_lossy().into_owned());
+        }
+        Ok(_) => {
+            // Mirror the target's absolute path inside the backup directory
+            let copy = paths.backup_file(format!("files/{}", target.trim_start_matches('/')));
+            if let Some(parent) = Path::new(&copy).parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&target, &copy)?;
+            original.file = Some(copy);
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    saved.push(original);
+    up_originals(paths, &saved)
+}
+/// Puts back every file raven has overwritten since the last restore, and removes the files it
+/// created. Returns the restored targets
+pub fn restore_original(paths: &RavenPaths) -> Result<Vec<String>, RavenError> {
+    let saved = originals(paths)?;
+    let mut restored = Vec::new();
+    for original in saved {
+        if fs::symlink_metadata(&original.target).is_ok() {
+            fs::remove_file(&original.target)?;
+        }
+        if let Some(ref file) = original.file {
+            fs::copy(file, &original.target)?;
+        } else if let Some(ref link) = original.link {
+            symlink(link, &original.target)?;
+        }
+        restored.push(original.target);
+    }
+    if fs::metadata(paths.backup()).is_ok() {
+        fs::remove_dir_all(paths.backup())?;
+    }
+    Ok(restored)
+}
@@ -0,0 +1,150 @@
+use error::RavenError;
+use paths::RavenPaths;
+use runner::{Cmd, CommandRunner};
+use std::{fs, io};
+use themes::{add_to_theme, del_theme, new_theme};
+/// An option found on the running desktop
+#[derive(Clone, Debug)]
+pub struct Found {
+    pub option: String,
+    /// Where it was read from, for showing to the user
+    pub source: String,
+    pub contents: Vec<u8>,
+    /// The base_ file removed from the start of the contents, if there was one
+    pub base: Option<String>,
+}
+/// Config files raven writes, with the option each belongs to and its base_ file
+const TARGETS: [(&str, &str, Option<&str>); 8] = [
+    ("i3", "i3/config", Some("base_i3")),
+    ("poly", "polybar/config", None),
+    ("rofi", "rofi/theme.rasi", None),
+    ("dunst", "dunst/dunstrc", Some("base_dunst")),
+    ("ranger", "ranger/rc.conf", None),
+    ("termite", "termite/config", None),
+    ("bspwm", "bspwm/bspwmrc", Some("base_bspwm")),
+    ("openbox", "openbox/rc.xml", Some("base_rc.xml")),
+];
+/// Reads a file, or None if it doesn't exist
+fn read_if_exists(path: &str) -> Result<Option<Vec<u8>>, RavenError> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+/// Removes a base_ file from the start of a config, as loading the theme appends to it again.
+/// Returns whether it was removed
+fn strip_base(paths: &RavenPaths, base: &str, contents: &mut Vec<u8>) -> Result<bool, RavenError> {
+    match read_if_exists(&paths.root_file(base))? {
+        Some(ref base) if !base.is_empty() && contents.starts_with(base) => {
+            contents.drain(..base.len());
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+/// Finds the wallpaper set by the last feh command in a .fehbg script
+fn fehbg_wallpaper(script: &str) -> Option<String> {
+    let line = script
+        .lines()
+        .rev()
+        .find(|x| x.trim_start().starts_with("feh"))?;
+    // feh quotes the paths it writes
+    match line.split('\'').nth(1) {
+        Some(path) => Some(path.to_string()),
+        None => line
+            .split_whitespace()
+            .skip(1)
+            .filter(|x| !x.starts_with('-'))
+            .last()
+            .map(|x| x.to_string()),
+    }
+}
+/// Collects the configs of the running desktop that raven has options for: the files it writes,
+/// the feh wallpaper and the loaded Xresources
+pub fn capture(paths: &RavenPaths, runner: &dyn CommandRunner) -> Result<Vec<Found>, RavenError> {
+    let mut found = Vec::new();
+    for &(option, target, base) in TARGETS.iter() {
+        let source = paths.target(target);
+        if let Some(mut contents) = read_if_exists(&source)? {
+            let base = match base {
+                Some(base) if strip_base(paths, base, &mut contents)? => Some(base.to_string()),
+                _ => None,
+            };
+            found.push(Found {
+                option: option.to_string(),
+                source,
+                contents,
+                base,
+            });
+        }
+    }
+    let ncmpcpp = [
+        paths.target("ncmpcpp/config"),
+        paths.home.clone() + "/.ncmpcpp/config",
+    ];
+    for source in ncmpcpp.iter() {
+        if let Some(contents) = read_if_exists(source)? {
+            found.push(Found {
+                option: "ncmpcpp".to_string(),
+                source: source.clone(),
+                contents,
+                base: None,
+            });
+            break;
+        }
+    }
+    let fehbg = read_if_exists(&(paths.home.clone() + "/.fehbg"))?;
+    let wallpaper = fehbg.and_then(|x| fehbg_wallpaper(&String::from_utf8_lossy(&x)));
+    if let Some(source) = wallpaper {
+        if let Some(contents) = read_if_exists(&paths.expand(source.as_str()))? {
+            found.push(Found {
+                option: "wall".to_string(),
+                source,
+                contents,
+                base: None,
+            });
+        }
+    }
+    // Without xrdb or a running X server there are no Xresources to capture
+    if let Ok(out) = runner.output(&Cmd::new("xrdb").arg("-query")) {
+        if out.success() && !out.stdout.trim().is_empty() {
+            found.push(Found {
+                option: "xres".to_string(),
+                source: "xrdb -query".to_string(),
+                contents: out.stdout.into_bytes(),
+                base: None,
+            });
+        }
+    }
+    Ok(found)
+}
+/// Creates a new theme from captured options. Nothing is left behind if one of them can't be
+/// added
+pub fn capture_theme<N>(
+    paths: &RavenPaths,
+    theme_name: N,
+    found: &[Found],
+) -> Result<(), RavenError>
+where
+    N: Into<String>,
+{
+    let theme_name = theme_name.into();
+    new_theme(paths, theme_name.as_str())?;
+    let staging = paths.cache_file("capture");
+    let res = fs::create_dir_all(&staging)
+        .map_err(RavenError::from)
+        .and_then(|_| {
+            found.iter().try_for_each(|x| {
+                let file = staging.clone() + "/" + &x.option;
+                fs::write(&file, &x.contents)?;
+                add_to_theme(paths, theme_name.clone(), x.option.clone(), file)
+            })
+        });
+    // Cleaning up is best effort, since the original error is what gets reported
+    let _ = fs::remove_dir_all(&staging);
+    if res.is_err() {
+        let _ = del_theme(paths, theme_name.as_str());
+    }
+    res
+}
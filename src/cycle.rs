@@ -0,0 +1,329 @@
+use config::{load_store, Playlist, PlaylistOrder};
+use error::RavenError;
+use paths::RavenPaths;
+use rand::{thread_rng, Rng};
+use serde_json;
+use std::{
+    fs, io,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+};
+use themes::get_themes;
+/// Number of themes kept for going back with `prev` in shuffle and weighted order
+const HISTORY: usize = 50;
+/// A command sent to ravend over its control socket, as one line of JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Switch to the next theme now
+    Next,
+    /// Switch back to the previous theme now
+    Prev,
+    /// Stop switching themes until resumed
+    Pause,
+    /// Continue switching themes, with the time that was left when paused
+    Resume,
+    /// Report the daemon's state without changing it
+    Status,
+    /// Change the number of seconds between themes
+    SetInterval { seconds: u64 },
+}
+/// What ravend is doing
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CycleStatus {
+    /// The playlist being cycled through, or None for all themes
+    #[serde(default)]
+    pub playlist: Option<String>,
+    /// The theme ravend loaded last
+    pub theme: Option<String>,
+    /// The rule that matches, which holds its theme in place of the playlist's
+    #[serde(default)]
+    pub rule: Option<String>,
+    pub paused: bool,
+    /// Seconds between themes
+    pub interval: u64,
+    /// Seconds until the next switch
+    pub remaining: u64,
+}
+/// ravend's answer to a request, as one line of JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Response {
+    Status(CycleStatus),
+    Error(String),
+}
+/// Sends a request to the running ravend and waits for its answer
+pub fn send(paths: &RavenPaths, request: &Request) -> Result<CycleStatus, RavenError> {
+    let mut stream = match UnixStream::connect(paths.socket()) {
+        Ok(stream) => stream,
+        Err(ref e)
+            if e.kind() == io::ErrorKind::NotFound
+                || e.kind() == io::ErrorKind::ConnectionRefused =>
+        {
+            return Err(RavenError::Invalid("Cycle daemon not running.".to_string()));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    stream.write_all((serde_json::to_string(request)? + "\n").as_bytes())?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    match serde_json::from_str(&line)? {
+        Response::Status(status) => Ok(status),
+        Response::Error(e) => Err(RavenError::Invalid(e)),
+    }
+}
+/// The themes of a playlist that can be loaded, in the order they are cycled through
+/// sequentially. Entries without a readable theme.json are skipped
+pub fn playlist_themes(paths: &RavenPaths, playlist: &Playlist) -> Result<Vec<String>, RavenError> {
+    let mut all = get_themes(paths)?
+        .into_iter()
+        .filter_map(|x| load_store(paths, x.as_str()).ok())
+        .collect::<Vec<_>>();
+    all.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut themes = Vec::new();
+    if playlist.themes.is_empty() && playlist.tags.is_empty() {
+        themes.extend(all.iter().map(|x| x.name.clone()));
+    }
+    for theme in &playlist.themes {
+        if all.iter().any(|x| x.name == *theme) && !themes.contains(theme) {
+            themes.push(theme.clone());
+        }
+    }
+    for store in &all {
+        if store.tags.iter().any(|x| playlist.tags.contains(x)) && !themes.contains(&store.name) {
+            themes.push(store.name.clone());
+        }
+    }
+    themes.retain(|x| !playlist.exclude.contains(x));
+    Ok(themes)
+}
+/// Where ravend is in its playlist. Saved in the cache directory, so a restarted ravend carries
+/// on from there
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    /// The playlist being cycled through, or None for all themes
+    pub playlist: Option<String>,
+    /// Themes loaded so far, followed by the rest of the current shuffle
+    pub queue: Vec<String>,
+    /// Index of the current theme in the queue
+    pub index: usize,
+}
+impl Position {
+    /// A position before the first theme of a playlist
+    pub fn new(playlist: Option<String>) -> Position {
+        Position {
+            playlist,
+            ..Position::default()
+        }
+    }
+    /// Reads the saved position, if it is for the given playlist
+    pub fn load(paths: &RavenPaths, playlist: Option<&str>) -> Position {
+        fs::read_to_string(paths.cache_file("cycle.json"))
+            .ok()
+            .and_then(|x| serde_json::from_str::<Position>(&x).ok())
+            .filter(|x| x.playlist.as_deref() == playlist)
+            .unwrap_or_else(|| Position::new(playlist.map(|x| x.to_string())))
+    }
+    pub fn save(&self, paths: &RavenPaths) -> Result<(), RavenError> {
+        fs::create_dir_all(&paths.cache)?;
+        fs::write(paths.cache_file("cycle.json"), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+    pub fn current(&self) -> Option<&str> {
+        self.queue.get(self.index).map(|x| x.as_str())
+    }
+    /// Moves to the theme after the current one, out of the playlist's themes. None if there is
+    /// no theme that may be picked, leaving the position as it is
+    pub fn next(&mut self, playlist: &Playlist, themes: &[String]) -> Option<String> {
+        if themes.is_empty() {
+            return None;
+        }
+        if playlist.order == PlaylistOrder::Sequential {
+            let next = match self.current() {
+                Some(current) => match themes.iter().position(|x| x == current) {
+                    Some(i) => themes.get(i + 1),
+                    // Carry on in name order from a theme that is no longer in the playlist
+                    None => themes.iter().find(|x| x.as_str() > current),
+                },
+                None => None,
+            };
+            let next = next.unwrap_or(&themes[0]).clone();
+            self.queue = vec![next.clone()];
+            self.index = 0;
+            return Some(next);
+        }
+        match self.find(self.index + 1..self.queue.len(), themes) {
+            Some(i) => self.index = i,
+            None => {
+                let picked = self.pick(playlist, themes);
+                if picked.is_empty() {
+                    return None;
+                }
+                if !self.queue.is_empty() {
+                    self.queue.truncate(self.index + 1);
+                    self.index += 1;
+                }
+                self.queue.extend(picked);
+            }
+        }
+        self.trim();
+        self.current().map(|x| x.to_string())
+    }
+    /// Moves to the theme before the current one. None if there is no earlier theme, as at the
+    /// start of a shuffle
+    pub fn prev(&mut self, playlist: &Playlist, themes: &[String]) -> Option<String> {
+        if themes.is_empty() {
+            return None;
+        }
+        match playlist.order {
+            PlaylistOrder::Sequential => {
+                let prev = match self.current() {
+                    Some(current) => match themes.iter().position(|x| x == current) {
+                        Some(i) if i > 0 => themes.get(i - 1),
+                        Some(_) => themes.last(),
+                        None => themes
+                            .iter()
+                            .rev()
+                            .find(|x| x.as_str() < current)
+                            .or_else(|| themes.last()),
+                    },
+                    None => themes.last(),
+                };
+                let prev = prev?.clone();
+                self.queue = vec![prev.clone()];
+                self.index = 0;
+                Some(prev)
+            }
+            _ => {
+                let i = self.find((0..self.index).rev(), themes)?;
+                self.index = i;
+                Some(self.queue[i].clone())
+            }
+        }
+    }
+    /// The first index in the queue, out of the given ones, of a theme still in the playlist
+    fn find<I>(&self, indices: I, themes: &[String]) -> Option<usize>
+    where
+        I: Iterator<Item = usize>,
+    {
+        let mut indices = indices;
+        indices.find(|i| themes.contains(&self.queue[*i]))
+    }
+    /// New themes to add after the end of the queue: a whole shuffle, or one theme picked by
+    /// weight. The current theme isn't picked right away again, unless it is the only one.
+    /// Themes weighted 0 are never picked, so this is empty if every other theme is
+    fn pick(&self, playlist: &Playlist, themes: &[String]) -> Vec<String> {
+        let mut rng = thread_rng();
+        let current = self.current();
+        if playlist.order == PlaylistOrder::Shuffle {
+            let mut shuffle = themes.to_vec();
+            rng.shuffle(&mut shuffle);
+            if shuffle.len() > 1 && current == Some(shuffle[0].as_str()) {
+                let last = shuffle.len() - 1;
+                shuffle.swap(0, last);
+            }
+            return shuffle;
+        }
+        let weight = |x: &String| *playlist.weights.get(x).unwrap_or(&1) as u64;
+        let candidates = themes
+            .iter()
+            .filter(|x| themes.len() == 1 || current != Some(x.as_str()))
+            .filter(|x| weight(x) > 0)
+            .collect::<Vec<_>>();
+        let total = candidates.iter().map(|x| weight(x)).sum::<u64>();
+        if total == 0 {
+            return Vec::new();
+        }
+        let mut left = rng.gen_range(0, total);
+        for theme in candidates {
+            if left < weight(theme) {
+                return vec![theme.clone()];
+            }
+            left -= weight(theme);
+        }
+        Vec::new()
+    }
+    /// Drops themes from the start of the queue beyond what `prev` can go back to
+    fn trim(&mut self) {
+        if self.index > HISTORY {
+            let drop = self.index - HISTORY;
+            self.queue.drain(..drop);
+            self.index -= drop;
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn playlist(order: PlaylistOrder, weights: &[(&str, u32)]) -> Playlist {
+        Playlist {
+            order,
+            weights: weights.iter().map(|&(k, v)| (k.to_string(), v)).collect(),
+            ..Playlist::default()
+        }
+    }
+    fn themes(names: &[&str]) -> Vec<String> {
+        names.iter().map(|x| x.to_string()).collect()
+    }
+    #[test]
+    fn sequential_wraps_around_both_ways() {
+        let playlist = playlist(PlaylistOrder::Sequential, &[]);
+        let themes = themes(&["a", "b", "c"]);
+        let mut pos = Position::new(None);
+        let forward = (0..4)
+            .map(|_| pos.next(&playlist, &themes).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(forward, ["a", "b", "c", "a"]);
+        let back = (0..2)
+            .map(|_| pos.prev(&playlist, &themes).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(back, ["c", "b"]);
+        let mut pos = Position::new(None);
+        assert_eq!(pos.prev(&playlist, &themes).unwrap(), "c");
+    }
+    #[test]
+    fn weighted_never_picks_weight_zero() {
+        let playlist = playlist(PlaylistOrder::Weighted, &[("b", 0)]);
+        let themes = themes(&["a", "b", "c"]);
+        let mut pos = Position::new(None);
+        for _ in 0..200 {
+            assert_ne!(pos.next(&playlist, &themes).unwrap(), "b");
+        }
+        // With only weight-0 themes left to switch to there is nothing to pick
+        let playlist = self::playlist(PlaylistOrder::Weighted, &[("b", 0), ("c", 0)]);
+        let mut pos = Position::new(None);
+        assert_eq!(pos.next(&playlist, &themes).unwrap(), "a");
+        assert_eq!(pos.next(&playlist, &themes), None);
+        assert_eq!(pos.current(), Some("a"));
+    }
+    #[test]
+    fn single_candidate_stays_on_current_theme() {
+        let themes = themes(&["a"]);
+        for order in &[PlaylistOrder::Shuffle, PlaylistOrder::Weighted] {
+            let playlist = playlist(*order, &[]);
+            let mut pos = Position::new(None);
+            for _ in 0..3 {
+                assert_eq!(pos.next(&playlist, &themes).unwrap(), "a");
+            }
+        }
+    }
+    #[test]
+    fn trims_history_and_skips_removed_themes() {
+        let playlist = playlist(PlaylistOrder::Shuffle, &[]);
+        let all = themes(&["a", "b", "c"]);
+        let mut pos = Position::new(None);
+        for _ in 0..HISTORY * 2 {
+            pos.next(&playlist, &all).unwrap();
+        }
+        assert_eq!(pos.index, HISTORY);
+        // Themes removed from the playlist are passed over in both directions
+        let left = themes(&["a"]);
+        assert_eq!(pos.next(&playlist, &left).unwrap(), "a");
+        let index = pos.index;
+        while let Some(prev) = pos.prev(&playlist, &left) {
+            assert_eq!(prev, "a");
+            assert!(pos.index < index);
+        }
+        assert!(pos.queue[..pos.index].iter().all(|x| x != "a"));
+    }
+}
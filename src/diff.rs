@@ -0,0 +1,171 @@
+use error::RavenError;
+use std::{fs, io};
+/// Lines of unchanged context shown around each change
+const CONTEXT: usize = 3;
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+/// Largest table of lines matched up line by line. Bigger changes are shown as replacing every
+/// changed line, since the table takes memory for each pair of lines
+const MAX_TABLE: usize = 4_000_000;
+/// Matches up the lines of two texts through their longest common subsequence
+fn line_ops<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    // Lines the texts start and end with alike stay out of the table
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut ops = old[..prefix]
+        .iter()
+        .map(|x| (Op::Same, *x))
+        .collect::<Vec<_>>();
+    let (old_mid, new_mid) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    if (old_mid.len() + 1).saturating_mul(new_mid.len() + 1) > MAX_TABLE {
+        ops.extend(old_mid.iter().map(|x| (Op::Removed, *x)));
+        ops.extend(new_mid.iter().map(|x| (Op::Added, *x)));
+    } else {
+        ops.extend(lcs_ops(old_mid, new_mid));
+    }
+    ops.extend(old[old.len() - suffix..].iter().map(|x| (Op::Same, *x)));
+    ops
+}
+/// Line by line matching, with a table of the common subsequence of every pair of tails
+fn lcs_ops<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let (n, m) = (old.len(), new.len());
+    // lcs[i][j] is the length of the common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            ops.push((Op::Same, old[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push((Op::Removed, old[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Added, new[j]));
+            j += 1;
+        }
+    }
+    ops
+}
+/// Produces a unified diff between two texts. Empty if they are the same
+pub fn unified<N, S>(old: &str, new: &str, old_name: N, new_name: S) -> String
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let old_lines = old.lines().collect::<Vec<&str>>();
+    let new_lines = new.lines().collect::<Vec<&str>>();
+    let ops = line_ops(&old_lines, &new_lines);
+    let changed = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Op::Same)
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    if changed.is_empty() {
+        return String::new();
+    }
+    // Group changes whose context overlaps into hunks of op indices
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    let mut out = format!("--- {}\n+++ {}\n", old_name.into(), new_name.into());
+    for (start, end) in hunks {
+        // Line numbers in each file where the hunk starts
+        let old_start = ops[..start]
+            .iter()
+            .filter(|(op, _)| *op != Op::Added)
+            .count();
+        let new_start = ops[..start]
+            .iter()
+            .filter(|(op, _)| *op != Op::Removed)
+            .count();
+        let hunk = &ops[start..end];
+        let old_len = hunk.iter().filter(|(op, _)| *op != Op::Added).count();
+        let new_len = hunk.iter().filter(|(op, _)| *op != Op::Removed).count();
+        out += &format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + 1,
+            old_len,
+            new_start + 1,
+            new_len
+        );
+        for (op, line) in hunk {
+            let sign = match op {
+                Op::Same => ' ',
+                Op::Removed => '-',
+                Op::Added => '+',
+            };
+            out.push(sign);
+            out += line;
+            out.push('\n');
+        }
+    }
+    out
+}
+/// Diffs the current contents of a file against new contents for it. A missing file is treated
+/// as empty
+pub fn against_file<N>(target: N, contents: &[u8]) -> Result<String, RavenError>
+where
+    N: Into<String>,
+{
+    let target = target.into();
+    let (old, old_name) = match fs::read(&target) {
+        Ok(old) => (old, target.clone()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => (Vec::new(), "/dev/null".to_string()),
+        Err(e) => return Err(e.into()),
+    };
+    if old == contents {
+        return Ok(String::new());
+    }
+    match (String::from_utf8(old), String::from_utf8(contents.to_vec())) {
+        (Ok(old), Ok(new)) => Ok(unified(&old, &new, old_name, target)),
+        _ => Ok(format!("Binary file {} changed\n", target)),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn large_changes_replace_whole_lines() {
+        let old = (0..3000).map(|x| format!("{}\n", x)).collect::<String>();
+        let new = (0..3000)
+            .map(|x| format!("{}\n", x + 5000))
+            .collect::<String>();
+        let old = "head\n".to_string() + &old + "tail\n";
+        let new = "head\n".to_string() + &new + "tail\n";
+        let diff = unified(&old, &new, "a", "b");
+        assert!(diff.starts_with("--- a\n+++ b\n@@ -1,3002 +1,3002 @@\n head\n-0\n-1\n"));
+        assert!(diff.ends_with("+7999\n tail\n"));
+        let small = unified("a\nb\nc\n", "a\nx\nc\n", "a", "b");
+        assert_eq!(small, "--- a\n+++ b\n@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n");
+    }
+}
@@ -0,0 +1,92 @@
+use image;
+use reqwest;
+use serde_json;
+use std::{error, fmt, io};
+/// Errors returned by ravenlib
+#[derive(Debug)]
+pub enum RavenError {
+    /// Reading or writing a file, or running a program, failed
+    Io(io::Error),
+    /// A config, theme or server response couldn't be (de)serialized
+    Json(serde_json::Error),
+    /// The named theme doesn't exist
+    MissingTheme(String),
+    /// A theme with this name already exists
+    ThemeExists(String),
+    /// A theme doesn't have the given option, or its option file is missing
+    MissingOption(String, String),
+    /// Couldn't talk to the ravenserver instance
+    Request(reqwest::Error),
+    /// The ravenserver instance answered with an unsuccessful status
+    Status(reqwest::StatusCode, String),
+    /// Not logged in, or not allowed to do this
+    Auth(String),
+    /// The theme has been reported and not yet approved by an admin
+    Reported(String),
+    /// An image couldn't be read or decoded
+    Image(image::ImageError),
+    /// Loading an option failed, and the changes made to load the theme were undone
+    LoadFailed(String, Box<RavenError>),
+    /// Invalid input or state
+    Invalid(String),
+}
+impl fmt::Display for RavenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RavenError::Io(e) => write!(f, "I/O error: {}", e),
+            RavenError::Json(e) => write!(f, "Couldn't parse JSON: {}", e),
+            RavenError::MissingTheme(name) => write!(f, "Theme {} does not exist", name),
+            RavenError::ThemeExists(name) => write!(f, "Theme {} already exists", name),
+            RavenError::MissingOption(theme, option) => {
+                write!(f, "Theme {} has no option {}", theme, option)
+            }
+            RavenError::Request(e) => write!(f, "Couldn't reach server: {}", e),
+            RavenError::Status(code, msg) => write!(f, "{}. Code {}", msg, code),
+            RavenError::Auth(msg) => write!(f, "{}", msg),
+            RavenError::Reported(name) => write!(
+                f,
+                "Theme {} has recently been reported, and has not been approved by an admin",
+                name
+            ),
+            RavenError::Image(e) => write!(f, "Couldn't read image: {}", e),
+            RavenError::LoadFailed(option, e) => write!(
+                f,
+                "Loading option {} failed, so the previous theme was restored: {}",
+                option, e
+            ),
+            RavenError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+impl error::Error for RavenError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RavenError::Io(e) => Some(e),
+            RavenError::Json(e) => Some(e),
+            RavenError::Request(e) => Some(e),
+            RavenError::Image(e) => Some(e),
+            RavenError::LoadFailed(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for RavenError {
+    fn from(e: io::Error) -> RavenError {
+        RavenError::Io(e)
+    }
+}
+impl From<serde_json::Error> for RavenError {
+    fn from(e: serde_json::Error) -> RavenError {
+        RavenError::Json(e)
+    }
+}
+impl From<reqwest::Error> for RavenError {
+    fn from(e: reqwest::Error) -> RavenError {
+        RavenError::Request(e)
+    }
+}
+impl From<image::ImageError> for RavenError {
+    fn from(e: image::ImageError) -> RavenError {
+        RavenError::Image(e)
+    }
+}
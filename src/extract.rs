@@ -0,0 +1,180 @@
+use error::RavenError;
+use image;
+use palette::Palette;
+use paths::RavenPaths;
+use serde_json;
+use std::{cmp::Reverse, fs, io::Read};
+/// Number of colors picked out of an image before they are turned into a palette
+const PICKED_COLORS: usize = 8;
+/// Images are scaled down to fit in a square of this size before colors are picked
+const SAMPLE_SIZE: u32 = 64;
+type Rgb = [u8; 3];
+/// Hashes file contents with 64-bit FNV-1a, which is stable across runs and platforms
+fn fnv_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+/// Perceived brightness of a color, from 0 to 255
+fn luminance(c: Rgb) -> u32 {
+    (299 * u32::from(c[0]) + 587 * u32::from(c[1]) + 114 * u32::from(c[2])) / 1000
+}
+/// Blends two colors. `amount` is how much of `to` is used, from 0 to 1
+fn blend(from: Rgb, to: Rgb, amount: f32) -> Rgb {
+    let mut out = [0; 3];
+    for i in 0..3 {
+        let mixed = f32::from(from[i]) * (1.0 - amount) + f32::from(to[i]) * amount;
+        out[i] = mixed.round() as u8;
+    }
+    out
+}
+fn hex(c: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+}
+/// The channel with the widest spread of values in a set of pixels, and that spread
+fn widest_channel(pixels: &[Rgb]) -> (usize, u8) {
+    let mut widest = (0, 0);
+    for ch in 0..3 {
+        let min = pixels.iter().map(|p| p[ch]).min().unwrap_or(0);
+        let max = pixels.iter().map(|p| p[ch]).max().unwrap_or(0);
+        if max - min > widest.1 {
+            widest = (ch, max - min);
+        }
+    }
+    widest
+}
+/// Average color of a set of pixels
+fn average(pixels: &[Rgb]) -> Rgb {
+    let mut sum = [0u64; 3];
+    for p in pixels {
+        for ch in 0..3 {
+            sum[ch] += u64::from(p[ch]);
+        }
+    }
+    let len = pixels.len().max(1) as u64;
+    [
+        (sum[0] / len) as u8,
+        (sum[1] / len) as u8,
+        (sum[2] / len) as u8,
+    ]
+}
+/// Picks up to `count` representative colors with median cut: the box of pixels with the widest
+/// channel is repeatedly split in two at its median, and each final box is averaged
+fn median_cut(pixels: Vec<Rgb>, count: usize) -> Vec<Rgb> {
+    let mut boxes = vec![pixels];
+    while boxes.len() < count {
+        let (index, channel) = match boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest_channel(b)))
+            .filter(|(_, (_, spread))| *spread > 0)
+            .max_by_key(|(i, (_, spread))| (*spread, Reverse(*i)))
+        {
+            Some((i, (ch, _))) => (i, ch),
+            None => break,
+        };
+        let mut split = boxes.remove(index);
+        // Sorting on the whole color as well keeps the split the same for equal inputs
+        split.sort_by_key(|p| (p[channel], *p));
+        let upper = split.split_off(split.len() / 2);
+        boxes.insert(index, upper);
+        boxes.insert(index, split);
+    }
+    boxes
+        .iter()
+        .filter(|b| !b.is_empty())
+        .map(|b| average(b))
+        .collect()
+}
+/// Generates a 16 color palette from the contents of an image file. The format is guessed from
+/// the contents, since option files have no extension. The same image always gives the same
+/// palette
+pub fn palette_from_image(bytes: &[u8]) -> Result<Palette, RavenError> {
+    let img = image::load_from_memory(bytes)?
+        .thumbnail(SAMPLE_SIZE, SAMPLE_SIZE)
+        .to_rgb();
+    let pixels = img.pixels().map(|p| p.data).collect::<Vec<Rgb>>();
+    if pixels.is_empty() {
+        return Err(RavenError::Invalid("Image has no pixels".to_string()));
+    }
+    let mut picked = median_cut(pixels, PICKED_COLORS);
+    picked.sort_by_key(|c| (luminance(*c), *c));
+    while picked.len() < PICKED_COLORS {
+        let last = picked[picked.len() - 1];
+        picked.push(last);
+    }
+    // Laid out like pywal's palettes: a darkened background, six accents, and a light foreground
+    let bg = blend(picked[0], [0, 0, 0], 0.5);
+    let fg = blend(picked[PICKED_COLORS - 1], [0xee, 0xee, 0xee], 0.5);
+    let mut palette = Palette::default();
+    palette.set("background", hex(bg))?;
+    palette.set("foreground", hex(fg))?;
+    palette.set("cursor", hex(fg))?;
+    palette.set("color0", hex(bg))?;
+    palette.set("color7", hex(fg))?;
+    palette.set("color8", hex(blend(fg, [0, 0, 0], 0.3)))?;
+    palette.set("color15", hex(fg))?;
+    for (i, color) in picked[1..PICKED_COLORS - 1].iter().enumerate() {
+        palette.set(format!("color{}", i + 1), hex(*color))?;
+        palette.set(format!("color{}", i + 9), hex(*color))?;
+    }
+    Ok(palette)
+}
+/// Generates a palette from an image, reusing the one cached for an image with the same contents.
+/// A newly generated palette is only added to the cache if `save` is set
+pub fn cached_palette<N>(paths: &RavenPaths, file: N, save: bool) -> Result<Palette, RavenError>
+where
+    N: Into<String>,
+{
+    let mut bytes = Vec::new();
+    fs::File::open(file.into())?.read_to_end(&mut bytes)?;
+    let cached = paths.cache_file(format!("palettes/{:016x}.json", fnv_hash(&bytes)));
+    if let Ok(contents) = fs::read_to_string(cached.as_str()) {
+        if let Ok(palette) = serde_json::from_str(&contents) {
+            return Ok(palette);
+        }
+    }
+    let palette = palette_from_image(&bytes)?;
+    if save {
+        fs::create_dir_all(paths.cache_file("palettes"))?;
+        fs::write(cached, serde_json::to_string(&palette)?)?;
+    }
+    Ok(palette)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageBuffer, ImageOutputFormat};
+    /// A PNG split into a navy top half and a red bottom half
+    fn two_tone_png() -> Vec<u8> {
+        let img = ImageBuffer::from_fn(SAMPLE_SIZE, SAMPLE_SIZE, |_, y| {
+            if y < SAMPLE_SIZE / 2 {
+                image::Rgb([0x10, 0x10, 0x30])
+            } else {
+                image::Rgb([0xc0, 0x20, 0x20])
+            }
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut bytes, ImageOutputFormat::PNG)
+            .unwrap();
+        bytes
+    }
+    #[test]
+    fn extraction_is_deterministic() {
+        let bytes = two_tone_png();
+        let first = palette_from_image(&bytes).unwrap();
+        let second = palette_from_image(&bytes).unwrap();
+        assert_eq!(first, second);
+        // The darker color becomes the background, darkened further, and the brighter one the
+        // accents and, lightened, the foreground
+        assert_eq!(first.get("background"), Some("#080818".to_string()));
+        assert_eq!(first.get("color1"), Some("#c02020".to_string()));
+        assert_eq!(first.get("color9"), Some("#c02020".to_string()));
+        assert_eq!(first.get("foreground"), Some("#d78787".to_string()));
+    }
+}
@@ -0,0 +1,548 @@
+use crate::config::{Config, CustomMode, CustomOption};
+use crate::error::RavenError;
+use crate::paths::RavenPaths;
+use crate::runner::Cmd;
+use crate::themes::Theme;
+use crate::transaction::Transaction;
+use std::{fs, io::Read};
+/// Applies one kind of theme option, such as a polybar config or an i3 config
+pub trait OptionLoader {
+    /// Name of the option, as listed in theme.json
+    fn name(&self) -> &str;
+    /// Whether the program this option themes is installed. Unavailable options are skipped
+    fn available(&self, _theme: &Theme) -> bool {
+        true
+    }
+    /// Stages writing the option's config files into place
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError>;
+    /// Stages the commands that make the running program pick up the new config
+    fn reload(&self, _theme: &Theme, _tx: &mut Transaction) -> Result<(), RavenError> {
+        Ok(())
+    }
+    /// Stages the commands that stop anything a previously loaded theme started for this option
+    fn cleanup(&self, _paths: &RavenPaths, _tx: &mut Transaction) -> Result<(), RavenError> {
+        Ok(())
+    }
+}
+/// Holds the option loaders raven knows about, keyed by option name
+#[derive(Default)]
+pub struct LoaderRegistry {
+    loaders: Vec<Box<dyn OptionLoader>>,
+}
+impl LoaderRegistry {
+    /// Creates a registry without any loaders
+    pub fn new() -> LoaderRegistry {
+        LoaderRegistry::default()
+    }
+    /// Creates a registry holding all of raven's built-in loaders
+    pub fn builtin() -> LoaderRegistry {
+        let mut reg = LoaderRegistry::new();
+        reg.register(Box::new(Poly));
+        reg.register(Box::new(I3 { wm: true }));
+        reg.register(Box::new(I3 { wm: false }));
+        reg.register(Box::new(Xres { merge: false }));
+        reg.register(Box::new(Xres { merge: true }));
+        reg.register(Box::new(Pywal));
+        reg.register(Box::new(Wall));
+        reg.register(Box::new(Ncmpcpp));
+        reg.register(Box::new(Termite));
+        reg.register(Box::new(Script));
+        reg.register(Box::new(Bspwm));
+        reg.register(Box::new(Rofi));
+        reg.register(Box::new(Ranger));
+        reg.register(Box::new(Lemonbar));
+        reg.register(Box::new(Openbox));
+        reg.register(Box::new(Dunst));
+        reg.register(Box::new(Sublt {
+            stype: "st_tmtheme",
+        }));
+        reg.register(Box::new(Sublt { stype: "st_scs" }));
+        reg.register(Box::new(Sublt {
+            stype: "st_subltheme",
+        }));
+        reg.register(Box::new(Vscode));
+        reg
+    }
+    /// Creates a registry holding the built-in loaders plus the custom options declared in the
+    /// config. Custom options replace built-in ones of the same name
+    pub fn with_config(conf: &Config) -> LoaderRegistry {
+        let mut reg = LoaderRegistry::builtin();
+        for option in &conf.custom {
+            reg.register(Box::new(Custom {
+                option: option.clone(),
+            }));
+        }
+        reg
+    }
+    /// Adds a loader, replacing any loader already registered under the same name
+    pub fn register(&mut self, loader: Box<dyn OptionLoader>) {
+        let name = loader.name().to_lowercase();
+        self.loaders.retain(|x| x.name().to_lowercase() != name);
+        self.loaders.push(loader);
+    }
+    /// Finds the loader for an option
+    pub fn get<N>(&self, name: N) -> Option<&dyn OptionLoader>
+    where
+        N: Into<String>,
+    {
+        let name = name.into().to_lowercase();
+        self.loaders
+            .iter()
+            .find(|x| x.name().to_lowercase() == name)
+            .map(|x| x.as_ref())
+    }
+    /// All registered loaders
+    pub fn loaders(&self) -> &[Box<dyn OptionLoader>] {
+        &self.loaders
+    }
+    /// Stages the cleanup of the registered loaders with the given names
+    pub fn cleanup(
+        &self,
+        paths: &RavenPaths,
+        names: &[String],
+        tx: &mut Transaction,
+    ) -> Result<(), RavenError> {
+        for loader in self
+            .loaders
+            .iter()
+            .filter(|x| names.iter().any(|n| n == x.name()))
+        {
+            tx.set_option(loader.name());
+            loader.cleanup(paths, tx)?;
+        }
+        Ok(())
+    }
+}
+/// Reads in a base_ file from the raven root, if it exists, and appends a theme's option to it.
+/// If the option is a template, both are rendered with the theme's variables
+fn with_base<N>(theme: &Theme, base: N, option: N) -> Result<String, RavenError>
+where
+    N: Into<String>,
+{
+    let option = option.into();
+    let mut config = String::new();
+    let base = theme.paths.root_file(base);
+    if fs::metadata(&base).is_ok() {
+        fs::File::open(&base)?.read_to_string(&mut config)?;
+        if theme.is_template(option.as_str()) {
+            config = theme.render(config)?;
+        }
+    }
+    config.push_str(&theme.read_option(option)?);
+    Ok(config)
+}
+/// Edits the value of a key in hjson files, staging the edited file
+fn edit_hjson<N, S, T>(tx: &mut Transaction, file: N, pat: S, value: T) -> Result<(), RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+    T: Into<String>,
+{
+    let file = &file.into();
+    let pat = &pat.into();
+    let value = &value.into();
+    let mut finals = String::new();
+    if let Some(pre) = tx.contents(file.as_str())? {
+        let mut patfound = false;
+        for line in pre.lines() {
+            if line.contains(pat) {
+                patfound = true;
+                if line.ends_with(",") {
+                    finals = finals + "\n" + "    " + pat + "\"" + &value + "\","
+                } else {
+                    finals = finals + "\n" + "    " + pat + "\"" + &value + "\""
+                }
+            } else if line.ends_with("}") && !patfound {
+                finals = finals + "," + "\n" + "    " + pat + "\"" + &value + "\"" + "\n" + line;
+            } else {
+                finals = finals + "\n" + line;
+            }
+        }
+        finals = finals.trim().to_string();
+    } else {
+        finals = finals + "{\n    " + pat + "\"" + &value + "\"\n}";
+    }
+    tx.write(file.as_str(), finals, 0o644);
+    Ok(())
+}
+/// Starts one polybar per monitor
+pub struct Poly;
+impl OptionLoader for Poly {
+    fn name(&self) -> &str {
+        "poly"
+    }
+    fn apply(&self, _theme: &Theme, _tx: &mut Transaction) -> Result<(), RavenError> {
+        Ok(())
+    }
+    fn reload(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let config = theme.rendered_path("poly")?;
+        for number in 0..theme.monitor {
+            let bar = theme.order.get(number as usize).ok_or_else(|| {
+                RavenError::Invalid(format!("No polybar bar configured for monitor {}", number))
+            })?;
+            tx.run(
+                Cmd::new("sh")
+                    .arg("-c")
+                    .arg(
+                        String::from("polybar --config=")
+                            + &config
+                            + " "
+                            + bar
+                            + " > /dev/null 2> /dev/null",
+                    )
+                    .background(),
+            );
+        }
+        Ok(())
+    }
+    fn cleanup(&self, _paths: &RavenPaths, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(Cmd::new("pkill").arg("polybar"));
+        Ok(())
+    }
+}
+/// Writes the i3 config, from either the `i3` or the `wm` option
+pub struct I3 {
+    pub wm: bool,
+}
+impl OptionLoader for I3 {
+    fn name(&self) -> &str {
+        if self.wm {
+            "wm"
+        } else {
+            "i3"
+        }
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let config = with_base(theme, "base_i3", self.name())?;
+        tx.write(theme.paths.target("i3/config"), config, 0o644);
+        Ok(())
+    }
+    fn reload(&self, _theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(Cmd::new("i3-msg").arg("reload"));
+        Ok(())
+    }
+}
+/// Loads Xresources, optionally merging them with the current ones
+pub struct Xres {
+    pub merge: bool,
+}
+impl OptionLoader for Xres {
+    fn name(&self) -> &str {
+        if self.merge {
+            "xres_m"
+        } else {
+            "xres"
+        }
+    }
+    fn apply(&self, _theme: &Theme, _tx: &mut Transaction) -> Result<(), RavenError> {
+        Ok(())
+    }
+    fn reload(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let mut xres = Cmd::new("xrdb");
+        if self.merge {
+            xres = xres.arg("-merge");
+        }
+        tx.run(xres.arg(theme.rendered_path(self.name())?));
+        Ok(())
+    }
+}
+/// Loads the color scheme generated from the theme's pywal image into xrdb. The colors are
+/// generated when the theme is loaded, and merged into its palette
+pub struct Pywal;
+impl OptionLoader for Pywal {
+    fn name(&self) -> &str {
+        "pywal"
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.write(
+            theme.paths.cache_file("colors.Xresources"),
+            theme.palette.export("Xresources", None)?,
+            0o644,
+        );
+        Ok(())
+    }
+    fn reload(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(
+            Cmd::new("xrdb")
+                .arg("-merge")
+                .arg(theme.paths.cache_file("colors.Xresources")),
+        );
+        Ok(())
+    }
+}
+/// Sets the wallpaper with feh
+pub struct Wall;
+impl OptionLoader for Wall {
+    fn name(&self) -> &str {
+        "wall"
+    }
+    fn apply(&self, _theme: &Theme, _tx: &mut Transaction) -> Result<(), RavenError> {
+        Ok(())
+    }
+    fn reload(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(
+            Cmd::new("feh")
+                .arg("--bg-scale")
+                .arg(theme.option_path("wall")?),
+        );
+        Ok(())
+    }
+}
+/// Copies in the ncmpcpp config
+pub struct Ncmpcpp;
+impl Ncmpcpp {
+    /// Finds the ncmpcpp config directory in use
+    fn dir(&self, paths: &RavenPaths) -> Option<String> {
+        if fs::metadata(paths.target("ncmpcpp")).is_ok() {
+            Some(paths.target("ncmpcpp"))
+        } else if fs::metadata(paths.home.clone() + "/.ncmpcpp").is_ok() {
+            Some(paths.home.clone() + "/.ncmpcpp")
+        } else {
+            None
+        }
+    }
+}
+impl OptionLoader for Ncmpcpp {
+    fn name(&self) -> &str {
+        "ncmpcpp"
+    }
+    fn available(&self, theme: &Theme) -> bool {
+        self.dir(&theme.paths).is_some()
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        if let Some(dir) = self.dir(&theme.paths) {
+            tx.copy(theme.rendered_path("ncmpcpp")?, dir + "/config")?;
+        }
+        Ok(())
+    }
+}
+/// Copies in the termite config
+pub struct Termite;
+impl OptionLoader for Termite {
+    fn name(&self) -> &str {
+        "termite"
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.copy(
+            theme.rendered_path("termite")?,
+            theme.paths.target("termite/config"),
+        )
+    }
+    fn reload(&self, _theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(Cmd::new("pkill").arg("-SIGUSR1").arg("termite").unchecked());
+        Ok(())
+    }
+}
+/// Runs a custom script
+pub struct Script;
+impl OptionLoader for Script {
+    fn name(&self) -> &str {
+        "script"
+    }
+    fn apply(&self, _theme: &Theme, _tx: &mut Transaction) -> Result<(), RavenError> {
+        Ok(())
+    }
+    fn reload(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(Cmd::new("sh").arg("-c").arg(theme.rendered_path("script")?));
+        Ok(())
+    }
+}
+/// Writes the bspwm config
+pub struct Bspwm;
+impl OptionLoader for Bspwm {
+    fn name(&self) -> &str {
+        "bspwm"
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let config = with_base(theme, "base_bspwm", "bspwm")?;
+        tx.write(theme.paths.target("bspwm/bspwmrc"), config, 0o744);
+        Ok(())
+    }
+    fn reload(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(
+            Cmd::new("sh")
+                .arg("-c")
+                .arg(theme.paths.target("bspwm/bspwmrc")),
+        );
+        Ok(())
+    }
+}
+/// Copies in a rofi theme
+pub struct Rofi;
+impl OptionLoader for Rofi {
+    fn name(&self) -> &str {
+        "rofi"
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.copy(
+            theme.rendered_path("rofi")?,
+            theme.paths.target("rofi/theme.rasi"),
+        )
+    }
+}
+/// Copies in the ranger config
+pub struct Ranger;
+impl OptionLoader for Ranger {
+    fn name(&self) -> &str {
+        "ranger"
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.copy(
+            theme.rendered_path("ranger")?,
+            theme.paths.target("ranger/rc.conf"),
+        )
+    }
+}
+/// Runs a script that starts lemonbar
+pub struct Lemonbar;
+impl OptionLoader for Lemonbar {
+    fn name(&self) -> &str {
+        "lemonbar"
+    }
+    fn apply(&self, _theme: &Theme, _tx: &mut Transaction) -> Result<(), RavenError> {
+        Ok(())
+    }
+    fn reload(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(
+            Cmd::new("sh")
+                .arg(theme.rendered_path("lemonbar")?)
+                .background(),
+        );
+        Ok(())
+    }
+    fn cleanup(&self, _paths: &RavenPaths, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(Cmd::new("pkill").arg("lemonbar"));
+        Ok(())
+    }
+}
+/// Writes the openbox rc.xml
+pub struct Openbox;
+impl OptionLoader for Openbox {
+    fn name(&self) -> &str {
+        "openbox"
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let config = with_base(theme, "base_rc.xml", "openbox")?;
+        tx.write(theme.paths.target("openbox/rc.xml"), config, 0o644);
+        Ok(())
+    }
+    fn reload(&self, _theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(Cmd::new("openbox").arg("--reconfigure"));
+        Ok(())
+    }
+}
+/// Writes the dunstrc and starts dunst
+pub struct Dunst;
+impl OptionLoader for Dunst {
+    fn name(&self) -> &str {
+        "dunst"
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let config = with_base(theme, "base_dunst", "dunst")?;
+        tx.write(theme.paths.target("dunst/dunstrc"), config, 0o644);
+        Ok(())
+    }
+    fn reload(&self, _theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(Cmd::new("dunst").background());
+        Ok(())
+    }
+    fn cleanup(&self, _paths: &RavenPaths, tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.run(Cmd::new("pkill").arg("dunst"));
+        Ok(())
+    }
+}
+/// Sets a sublime text 3 color scheme or theme, from a key-value option
+pub struct Sublt {
+    pub stype: &'static str,
+}
+impl OptionLoader for Sublt {
+    fn name(&self) -> &str {
+        self.stype
+    }
+    fn available(&self, theme: &Theme) -> bool {
+        fs::metadata(theme.paths.target("sublime-text-3/Packages/User")).is_ok()
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let path = theme.paths.target("sublime-text-3/Packages/User");
+        let mut value = theme.value(self.stype)?;
+        if value.starts_with("sublt/") {
+            value = value.trim_start_matches("sublt/").to_string();
+            // The file is a template if the key-value option pointing to it is
+            tx.copy(
+                theme.rendered_file(
+                    String::from("sublt/") + &value,
+                    theme.is_template(self.stype),
+                )?,
+                path.clone() + "/" + &value,
+            )?;
+        }
+        let pattern = if self.stype == "st_subltheme" {
+            "\"theme\": "
+        } else {
+            "\"color_scheme\": "
+        };
+        edit_hjson(tx, path + "/Preferences.sublime-settings", pattern, value)
+    }
+}
+/// Sets the VSCode color theme, from a key-value option
+pub struct Vscode;
+impl Vscode {
+    /// VSCode user config directories that exist
+    fn dirs(&self, paths: &RavenPaths) -> Vec<String> {
+        vec![paths.target("Code/User"), paths.target("Code - OSS/User")]
+            .into_iter()
+            .filter(|x| fs::metadata(x).is_ok())
+            .collect()
+    }
+}
+impl OptionLoader for Vscode {
+    fn name(&self) -> &str {
+        "vscode"
+    }
+    fn available(&self, theme: &Theme) -> bool {
+        !self.dirs(&theme.paths).is_empty()
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let value = theme.value("vscode")?;
+        for dir in self.dirs(&theme.paths) {
+            edit_hjson(
+                tx,
+                dir + "/settings.json",
+                "\"workbench.colorTheme\": ",
+                value.as_str(),
+            )?;
+        }
+        Ok(())
+    }
+}
+/// Puts a user-defined option's file into place, as declared in config.json
+pub struct Custom {
+    pub option: CustomOption,
+}
+impl OptionLoader for Custom {
+    fn name(&self) -> &str {
+        self.option.name.as_str()
+    }
+    fn apply(&self, theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        let dest = theme.paths.expand(self.option.path.as_str());
+        match self.option.mode {
+            CustomMode::Copy => tx.copy(theme.rendered_path(self.name())?, dest)?,
+            CustomMode::Append => {
+                let config = with_base(
+                    theme,
+                    String::from("base_") + self.name(),
+                    self.name().to_string(),
+                )?;
+                tx.write(dest, config, 0o644);
+            }
+            CustomMode::Symlink => tx.symlink(theme.rendered_path(self.name())?, dest),
+        }
+        Ok(())
+    }
+    fn reload(&self, _theme: &Theme, tx: &mut Transaction) -> Result<(), RavenError> {
+        if let Some(ref reload) = self.option.reload {
+            tx.run(Cmd::new("sh").arg("-c").arg(reload.as_str()));
+        }
+        Ok(())
+    }
+}
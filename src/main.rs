@@ -1,4 +1,4 @@
-use std::{env, fs, fs::DirEntry, io, process::Command};
+use std::{env, fs, fs::DirEntry, io, io::Write, process::exit, time::Duration};
 extern crate dirs;
 extern crate ravenlib;
 extern crate serde;
@@ -9,51 +9,158 @@ extern crate structopt;
 use structopt::StructOpt;
 pub mod args;
 use args::*;
-use dirs::home_dir;
-use ravenlib::{config::*, daemon::*, ravenserver::*, themes::*};
+use ravenlib::{
+    backup::restore_original,
+    capture::{capture, capture_theme},
+    config::*,
+    cycle,
+    daemon::*,
+    diff,
+    error::RavenError,
+    paths::RavenPaths,
+    proc_path,
+    ravenserver::*,
+    report::{LoadReport, Status},
+    rules::{matching_rule, sysfs_root, Facts},
+    runner::*,
+    themes::*,
+    transaction::Change,
+};
 fn main() {
-    if check_init() {
-        init();
+    let args = RavenArgs::from_args();
+    let paths = match RavenPaths::new(args.root) {
+        Ok(paths) => paths,
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
+    if check_init(&paths) {
+        match init(&paths) {
+            Ok(true) => println!(
+                "The config file format has changed. Please check {} to reconfigure raven.",
+                paths.config_file()
+            ),
+            Ok(false) => {}
+            Err(e) => {
+                println!("Couldn't initialize base config: {}", e);
+                exit(1);
+            }
+        }
+        println!("Correctly initialized base config. Please run again to use raven.");
+    }
+    if let Err(e) = interpet_args(&paths, &SystemRunner, args.command) {
+        println!("{}", e);
+        exit(1);
     }
-    interpet_args();
 }
-fn interpet_args() {
+fn interpet_args(
+    paths: &RavenPaths,
+    runner: &dyn CommandRunner,
+    r: Raven,
+) -> Result<(), RavenError> {
     //Interpet arguments and check for a need to run init()
-    let r = Raven::from_args();
     use Cycle::*;
     use Manage::*;
     use Raven::*;
-    check_themes();
+    check_themes(paths)?;
     //If a theme may be changing, kill the previous theme's processes. Currently only polybar
     //and lemonbar
-    let conf = get_config();
+    let conf = get_config(paths)?;
     match r {
-        Load { theme } => {
-            clear_prev();
-            run_theme(load_theme(theme).unwrap());
-        }
-        New { name } => new_theme(name),
-        Modify { name, editor } => modify_file(conf.editing, name, editor),
-        Delete { name } => del_theme(name),
-        Edit { name } => edit(name),
-        Key {key, value} => key_value(key, value, conf.editing),
+        Load {
+            theme,
+            dry_run,
+            variant,
+            select,
+        } => {
+            let mut theme = load_theme(paths, theme)?.with_selection(select.selection());
+            if let Some(variant) = variant {
+                theme = theme.with_variant(variant)?;
+            }
+            if dry_run {
+                print_plan(theme)?;
+            } else {
+                clear_prev(paths, &theme.selection, runner)?;
+                print_loaded(run_theme(theme, runner)?)?;
+            }
+        }
+        New { name, extends } => {
+            if let Some(ref parent) = extends {
+                load_store(paths, parent.as_str())?;
+            }
+            new_theme(paths, name.as_str())?;
+            if let Some(parent) = extends {
+                set_extends(paths, name.as_str(), parent)?;
+            }
+            println!("You are now editing the theme {}", name);
+        }
+        Compose { name, from } => {
+            compose_theme(paths, name.as_str(), &from)?;
+            println!("You are now editing the theme {}", name);
+        }
+        Modify { name, editor } => modify_file(paths, runner, conf.editing, name, editor)?,
+        Delete { name } => del_theme(paths, name)?,
+        Edit { name } => {
+            edit(paths, name.as_str())?;
+            println!("You are now editing the theme {}", name);
+        }
+        Key {
+            key,
+            value,
+            variant: Some(variant),
+        } => variant_key_value(paths, conf.editing, variant, key, value)?,
+        Key { key, value, .. } => key_value(paths, key, value, conf.editing)?,
         ManageO { .. } => {
             match r {
                 ManageO(Export { name }) => {
-                    export(name, check_tmp());
+                    println!("Wrote theme to {}", export(paths, name, check_tmp())?);
+                }
+                ManageO(Import { name }) => {
+                    import(paths, name)?;
+                    println!("Imported theme.");
+                }
+                ManageO(Publish { name }) => {
+                    if upload_theme(paths, name)? {
+                        println!("Theme successfully uploaded.");
+                    } else {
+                        println!("Theme successfully updated.");
+                    }
+                }
+                ManageO(Create { name, pass1, pass2 }) => {
+                    create_user(paths, name, pass1, pass2)?;
+                    println!("Successfully created user. Sign in with `raven login [name] [password]`");
+                }
+                ManageO(Unpublish { name }) => {
+                    unpublish_theme(paths, name)?;
+                    println!("Successfully unpublished theme");
+                }
+                ManageO(Login { name, pass }) => {
+                    login_user(paths, name, pass)?;
+                    println!("Successfully signed in. Wrote login info to disk.");
+                }
+                ManageO(Logout {}) => {
+                    logout(paths)?;
+                    println!("Successfully logged you out");
+                }
+                ManageO(DUser { pass }) => {
+                    delete_user(paths, pass)?;
+                    println!("Successfully deleted user and all owned themes. Logged out");
                 }
-                ManageO(Import { name }) => import(name),
-                ManageO(Publish { name }) => upload_theme(name),
-                ManageO(Create { name, pass1, pass2 }) => create_user(name, pass1, pass2),
-                ManageO(Unpublish { name }) => unpublish_theme(name),
-                ManageO(Login { name, pass }) => login_user(name, pass),
-                ManageO(Logout {}) => logout(),
-                ManageO(DUser { pass }) => delete_user(pass),
                 _ => println!("Well, this shouldn't be happening"),
             };
         }
+        CycleD(Next {}) => print_cycle(cycle::send(paths, &cycle::Request::Next)?),
+        CycleD(Prev {}) => print_cycle(cycle::send(paths, &cycle::Request::Prev)?),
+        CycleD(Pause {}) => print_cycle(cycle::send(paths, &cycle::Request::Pause)?),
+        CycleD(Resume {}) => print_cycle(cycle::send(paths, &cycle::Request::Resume)?),
+        CycleD(Status {}) => print_cycle(cycle::send(paths, &cycle::Request::Status)?),
+        CycleD(SetInterval { seconds }) => print_cycle(cycle::send(
+            paths,
+            &cycle::Request::SetInterval { seconds },
+        )?),
         CycleD { .. } => {
-            let running = check_daemon();
+            let running = check_daemon(paths)?;
             match r {
                 CycleD(Check {}) => {
                     if running {
@@ -62,16 +169,26 @@ fn interpet_args() {
                         println!("Cycle daemon not running.");
                     }
                 }
-                CycleD(Start {}) => {
+                CycleD(Start { playlist }) => {
                     if !running {
-                        start_daemon();
+                        if let Some(ref playlist) = playlist {
+                            if !conf.playlists.contains_key(playlist) {
+                                return Err(RavenError::Invalid(format!(
+                                    "There is no playlist named {}.",
+                                    playlist
+                                )));
+                            }
+                        }
+                        start_daemon(paths, runner, playlist)?;
+                        println!("Started cycle daemon.");
                     } else {
                         println!("Cycle daemon already running.");
                     }
                 }
                 CycleD(Stop {}) => {
                     if running {
-                        stop_daemon();
+                        stop_daemon(paths, runner)?;
+                        println!("Stopped cycle daemon.");
                     } else {
                         println!("Cycle daemon not running.");
                     }
@@ -81,66 +198,448 @@ fn interpet_args() {
                 }
             }
         }
-        Info {} => print_info(conf.editing),
-        Refresh {} => {
-            clear_prev();
-            refresh_theme(conf.last);
+        RulesO(RulesAction::Test {}) => test_rules(paths, runner, &conf)?,
+        PaletteO(PaletteAction::Set { key, value }) => {
+            palette_set(paths, conf.editing, key, value)?
+        }
+        PaletteO(PaletteAction::Show {}) => {
+            let store = load_store(paths, conf.editing)?;
+            if store.palette.is_empty() {
+                println!("Theme {} has no palette", store.name);
+            }
+            for (key, value) in store.palette.entries() {
+                println!("{}: {}", key, value);
+            }
+        }
+        PaletteO(PaletteAction::Import { file }) => {
+            let imported = palette_import(paths, conf.editing, file)?;
+            println!("Imported {} colors", imported.entries().len());
+        }
+        Info {} => print_info(paths, conf.editing)?,
+        Refresh { select } => {
+            let selection = select.selection();
+            clear_prev(paths, &selection, runner)?;
+            let variant = conf.last_variant().map(String::from);
+            print_loaded(refresh_theme(paths, conf.last, variant, selection, runner)?)?;
+        }
+        ToggleVariant {} => {
+            let theme = toggle_variant(paths)?;
+            match theme.variant {
+                Some(ref variant) => println!("Switching {} to variant {}", theme.name, variant),
+                None => println!("Switching {} back to no variant", theme.name),
+            }
+            clear_prev(paths, &theme.selection, runner)?;
+            print_loaded(run_theme(theme, runner)?)?;
+        }
+        Install { name, force } => install(paths, name, force)?,
+        Capture { name, yes } => capture_desktop(paths, runner, name, yes)?,
+        Add {
+            name,
+            option,
+            variant: Some(variant),
+        } => add_to_variant(paths, conf.editing, variant, option, name)?,
+        Add { name, option, .. } => add_to_theme(paths, conf.editing, option, name)?,
+        Rm { name } => rm_from_theme(paths, conf.editing, name)?,
+        Enable { option } => {
+            if enable_option(paths, conf.editing.as_str(), option.as_str())? {
+                println!("Enabled {} in {}", option, conf.editing);
+            } else {
+                println!("{} is already enabled in {}", option, conf.editing);
+            }
+        }
+        Disable { option } => {
+            if disable_option(paths, conf.editing.as_str(), option.as_str())? {
+                println!("Disabled {} in {}", option, conf.editing);
+            } else {
+                println!("{} is already disabled in {}", option, conf.editing);
+            }
+        }
+        Tag { tag } => {
+            if tag_theme(paths, conf.editing.as_str(), tag.as_str())? {
+                println!("Tagged {} with {}", conf.editing, tag);
+            } else {
+                println!("{} is already tagged with {}", conf.editing, tag);
+            }
+        }
+        Untag { tag } => {
+            if untag_theme(paths, conf.editing.as_str(), tag.as_str())? {
+                println!("Removed tag {} from {}", tag, conf.editing);
+            } else {
+                println!("{} isn't tagged with {}", conf.editing, tag);
+            }
+        }
+        Template { option } => {
+            if template_option(paths, conf.editing.as_str(), option.as_str())? {
+                println!("{} is now a template in {}", option, conf.editing);
+            } else {
+                println!("{} is already a template in {}", option, conf.editing);
+            }
+        }
+        Untemplate { option } => {
+            if untemplate_option(paths, conf.editing.as_str(), option.as_str())? {
+                println!("{} is no longer a template in {}", option, conf.editing);
+            } else {
+                println!("{} isn't a template in {}", option, conf.editing);
+            }
+        }
+        Menu { select } => show_menu(paths, runner, conf.menu_command, select.selection())?,
+        RestoreOriginal {} => {
+            clear_prev(paths, &Selection::default(), runner)?;
+            let restored = restore_original(paths)?;
+            if restored.is_empty() {
+                println!("Raven hasn't overwritten any files.");
+            }
+            for target in restored {
+                println!("Restored {}", target);
+            }
+        }
+    };
+    Ok(())
+}
+/// Prints a table of how each option of a theme that has just been loaded went. Fails if any
+/// option failed, so raven exits unsuccessfully
+fn print_loaded(report: LoadReport) -> Result<(), RavenError> {
+    let width = report
+        .options
+        .iter()
+        .map(|x| x.option.len())
+        .max()
+        .unwrap_or(0)
+        .max("option".len());
+    println!(
+        "{:width$}  {:7}  {:>8}",
+        "option",
+        "status",
+        "time",
+        width = width
+    );
+    for option in &report.options {
+        let (status, detail) = match option.status {
+            Status::Applied => ("ok", String::new()),
+            Status::Skipped(ref reason) => ("skipped", reason.clone()),
+            Status::Failed {
+                ref command,
+                code,
+                ref stderr,
+            } => {
+                let code = match code {
+                    Some(code) => format!("exited with {}", code),
+                    None => "killed by a signal".to_string(),
+                };
+                let mut detail = format!("{} {}", command, code);
+                if let Some(line) = stderr.lines().next() {
+                    detail += &format!(": {}", line);
+                }
+                ("failed", detail)
+            }
+        };
+        let line = format!(
+            "{:width$}  {:7}  {:>5} ms  {}",
+            option.option,
+            status,
+            millis(option.duration),
+            detail,
+            width = width
+        );
+        println!("{}", line.trim_end());
+    }
+    println!(
+        "Loaded theme {} in {} ms",
+        report.theme,
+        millis(report.duration())
+    );
+    let failed = report.failed_options();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(RavenError::Invalid(format!(
+            "Some options failed to load: {}",
+            failed.join(", ")
+        )))
+    }
+}
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+/// Prints what loading a theme would do
+fn print_plan(mut theme: Theme) -> Result<(), RavenError> {
+    let (tx, report) = plan_theme(&mut theme)?;
+    for action in tx.actions() {
+        match action.change {
+            Change::Write {
+                ref target,
+                ref contents,
+                ..
+            } => {
+                let diff = diff::against_file(target.as_str(), contents)?;
+                if diff.is_empty() {
+                    println!("[{}] Would write {} (unchanged)", action.option, target);
+                } else {
+                    println!("[{}] Would write {}", action.option, target);
+                    print!("{}", diff);
+                }
+            }
+            Change::Symlink {
+                ref target,
+                ref source,
+            } => println!("[{}] Would link {} to {}", action.option, target, source),
+            Change::Remove { ref target } => {
+                if fs::symlink_metadata(target).is_ok() {
+                    println!("[{}] Would remove {}", action.option, target);
+                }
+            }
+            Change::Run(ref cmd) => println!("[{}] Would run {}", action.option, cmd),
+        }
+    }
+    for option in report.skipped_options() {
+        println!("Would skip option {}", option);
+    }
+    println!("Dry run of theme {}, nothing was changed", theme.name);
+    Ok(())
+}
+fn install<N>(paths: &RavenPaths, name: N, force: bool) -> Result<(), RavenError>
+where
+    N: Into<String>,
+{
+    let name = name.into();
+    let script = match download_theme(paths, name.as_str(), force) {
+        Err(RavenError::Reported(_)) => {
+            print!(
+                "This theme has recently been reported, and has not been approved by an admin. It is not advisable to install this theme. Are you sure you would like to continue? (y/n)"
+            );
+            io::stdout().flush()?;
+            let mut r = String::new();
+            io::stdin().read_line(&mut r)?;
+            if r.trim() == "y" {
+                println!(
+                    "Continuing. Please look carefully at the theme files in {} before loading this theme.",
+                    paths.theme(name.as_str())
+                );
+                download_theme(paths, name.as_str(), true)?
+            } else {
+                println!("Not installing theme.");
+                return Ok(());
+            }
         }
-        Install { name, force } => download_theme(name, force),
-        Add { name, option } => add_to_theme(conf.editing, option, name),
-        Rm { name } => rm_from_theme(conf.editing, name),
-        Menu {} => show_menu(conf.menu_command),
+        res => res?,
     };
+    println!("Installed theme {}", name);
+    if !force {
+        install_warning(paths, script)?;
+    }
+    Ok(())
+}
+/// Prints what the rules are checked against, and which of them matches
+fn test_rules(
+    paths: &RavenPaths,
+    runner: &dyn CommandRunner,
+    conf: &Config,
+) -> Result<(), RavenError> {
+    if conf.rules.is_empty() {
+        println!("No rules are configured.");
+        return Ok(());
+    }
+    let facts = Facts::gather(&conf.rules, &conf.outputs_command, &sysfs_root(), runner)?;
+    println!(
+        "Time: {} {:02}:{:02}",
+        facts.day(),
+        facts.now.tm_hour,
+        facts.now.tm_min
+    );
+    println!("Power: {}", facts.power);
+    println!("Hostname: {}", facts.hostname);
+    if conf.rules.iter().any(|x| x.outputs.is_some()) {
+        match facts.outputs {
+            Some(outputs) => println!("Outputs: {}", outputs),
+            None => println!("Outputs: unknown, {} failed", conf.outputs_command),
+        }
+    }
+    match matching_rule(paths, &conf.rules, &facts)? {
+        Some(rule) => println!("Matching rule: {}, which loads {}", rule.name, rule.theme),
+        None => println!("No rule matches."),
+    }
+    Ok(())
+}
+/// Prints the state of the cycle daemon
+fn print_cycle(status: cycle::CycleStatus) {
+    if let Some(playlist) = status.playlist {
+        println!("Playlist: {}", playlist);
+    }
+    match status.theme {
+        Some(theme) => println!("Current theme: {}", theme),
+        None => println!("No theme loaded yet."),
+    }
+    if status.paused {
+        println!(
+            "Paused, with {} seconds left until the next theme.",
+            status.remaining
+        );
+    } else if let Some(rule) = status.rule {
+        println!(
+            "Rule {} matches, so the theme stays until it doesn't.",
+            rule
+        );
+    } else {
+        println!("Next theme in {} seconds.", status.remaining);
+    }
+    println!("Switching every {} seconds.", status.interval);
+}
+/// Shows what can be captured from the desktop, and creates a theme from it once confirmed
+fn capture_desktop<N>(
+    paths: &RavenPaths,
+    runner: &dyn CommandRunner,
+    name: N,
+    yes: bool,
+) -> Result<(), RavenError>
+where
+    N: Into<String>,
+{
+    let name = name.into();
+    if fs::metadata(paths.theme(name.as_str())).is_ok() {
+        return Err(RavenError::ThemeExists(name));
+    }
+    let found = capture(paths, runner)?;
+    if found.is_empty() {
+        println!("Couldn't find any configs to capture.");
+        return Ok(());
+    }
+    println!("Found:");
+    for option in &found {
+        match option.base {
+            Some(ref base) => println!(
+                "{}: {} (without the contents of {})",
+                option.option, option.source, base
+            ),
+            None => println!("{}: {}", option.option, option.source),
+        }
+    }
+    if !yes {
+        print!("Create theme {} with these options? (y/n)", name);
+        io::stdout().flush()?;
+        let mut r = String::new();
+        io::stdin().read_line(&mut r)?;
+        if r.trim() != "y" {
+            println!("Not creating theme.");
+            return Ok(());
+        }
+    }
+    capture_theme(paths, name.as_str(), &found)?;
+    println!("Created theme {}. You are now editing it.", name);
+    Ok(())
+}
+fn install_warning(paths: &RavenPaths, esp: bool) -> Result<(), RavenError> {
+    println!(
+        "Warning: When you install themes from the online repo, there is some danger. Please evaluate the theme files before loading the theme, and if you find any malicious theme, please report it on the theme's page at {} and it will be removed.",
+        get_host(paths)?
+    );
+    if esp {
+        println!(
+            "This theme should be scrutinized more carefully as it includes a bash script which will be run automatically."
+        );
+    }
+    println!("Thank you for helping keep the repo clean!");
+    Ok(())
 }
-
-fn print_info<N>(editing: N)
+fn print_info<N>(paths: &RavenPaths, editing: N) -> Result<(), RavenError>
 where
     N: Into<String>,
 {
     let editing = editing.into();
-    let options = fs::read_dir(get_home() + "/.config/raven/themes/" + &editing)
-        .expect("Couldn't read themes")
-        .collect::<Vec<io::Result<DirEntry>>>()
+    let (store, origins) = resolve_store(paths, editing.as_str())?;
+    let mut options = fs::read_dir(paths.theme(editing.as_str()))?
+        .collect::<io::Result<Vec<DirEntry>>>()?
         .into_iter()
-        .map(|x| proc_path(x.unwrap()))
-        .filter(|x| x != "theme.json")
+        .map(proc_path)
+        .filter(|x| x != "theme.json" && x != "variants")
         .collect::<Vec<String>>();
+    // Inherited options have no file in the theme's own directory
+    options.extend(
+        store
+            .options
+            .iter()
+            .filter(|x| *x != "|" && origins.get(*x) != Some(&editing))
+            .cloned(),
+    );
+    if let Some(ref parent) = store.extends {
+        println!("{} extends {}", editing, parent);
+    }
     println!("Current configured options for {}", editing);
     for option in options {
-        println!("{}", option);
+        let mut line = option.clone();
+        match origins.get(&option) {
+            Some(origin) if *origin != editing => line += &format!(" (from {})", origin),
+            _ => {}
+        }
+        if !store.is_enabled(option.as_str()) {
+            line += " (disabled)";
+        }
+        println!("{}", line);
+    }
+    for (key, value) in &store.kv {
+        let mut line = match value {
+            serde_json::Value::String(value) => format!("{} = {}", key, value),
+            value => format!("{} = {}", key, value),
+        };
+        match origins.get(key) {
+            Some(origin) if *origin != editing => line += &format!(" (from {})", origin),
+            _ => {}
+        }
+        if !store.is_enabled(key.as_str()) {
+            line += " (disabled)";
+        }
+        println!("{}", line);
+    }
+    if !store.tags.is_empty() {
+        println!("Tags: {}", store.tags.join(", "));
+    }
+    if !store.variants.is_empty() {
+        let variants = store.variants.keys().cloned().collect::<Vec<String>>();
+        println!("Variants: {}", variants.join(", "));
     }
     println!("All themes: ");
-    let themes = get_themes();
+    let themes = get_themes(paths)?;
     for t in themes {
         println!("{}", t);
     }
+    Ok(())
 }
-fn modify_file<N>(editing: N, file: N, editor: Option<N>)
+fn modify_file<N>(
+    paths: &RavenPaths,
+    runner: &dyn CommandRunner,
+    editing: N,
+    file: N,
+    editor: Option<N>,
+) -> Result<(), RavenError>
 where
     N: Into<String>,
 {
     //Pulls $EDITOR from environment variables
-    if editor.is_none() {
-        let editor = env::var_os("EDITOR");
-        if editor.is_none() {
-            println!("Could not fetch $EDITOR from your OS.");
-            std::process::exit(64);
-        }
-    }
-    let editor = editor.unwrap().into();
-    let path = get_home() + "/.config/raven/themes/" + &editing.into() + "/" + &file.into();
+    let editor = match editor {
+        Some(editor) => editor.into(),
+        None => match env::var("EDITOR") {
+            Ok(editor) => editor,
+            Err(_) => {
+                println!("Could not fetch $EDITOR from your OS.");
+                exit(64);
+            }
+        },
+    };
+    let path = paths.theme_file(editing, file);
     println!("Started {:?} at {}", editor, path);
-    Command::new(editor)
-        .arg(path)
-        .spawn()
-        .expect("Couldn't run $EDITOR");
+    runner.spawn(&Cmd::new(editor).arg(path))?;
+    Ok(())
 }
-fn show_menu<N>(menu_command: N)
+fn show_menu<N>(
+    paths: &RavenPaths,
+    runner: &dyn CommandRunner,
+    menu_command: N,
+    selection: Selection,
+) -> Result<(), RavenError>
 where
     N: Into<String>,
 {
     let mut theme_list = String::new();
-    let mut entries = get_themes();
+    let mut entries = get_themes(paths)?;
     entries.sort_by(|a, b| a.cmp(&b));
     let mut i = 0;
     for entry in entries {
@@ -152,30 +651,18 @@ where
             i += 1;
         }
     }
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(String::from("echo '") + &theme_list + "' | " + &menu_command.into())
-        .output()
-        .expect("Failed to run menu.");
-    let int_output = String::from_utf8_lossy(&output.stdout);
+    let output = runner.output(
+        &Cmd::new("sh")
+            .arg("-c")
+            .arg(String::from("echo '") + &theme_list + "' | " + &menu_command.into()),
+    )?;
+    let int_output = output.stdout;
     if int_output.len() > 0 {
-        clear_prev();
-        let theme = load_theme(int_output.trim());
-        if theme.is_err() {
-            println!("Could not load in theme data. Does it exist?");
-        } else {
-            run_theme(theme.unwrap());
-        }
+        let theme = load_theme(paths, int_output.trim())?.with_selection(selection);
+        clear_prev(paths, &theme.selection, runner)?;
+        print_loaded(run_theme(theme, runner)?)?;
     } else {
         println!("Theme not selected.");
     }
-}
-
-fn get_home() -> String {
-    return String::from(home_dir().unwrap().to_str().unwrap());
-}
-fn proc_path(path: DirEntry) -> String {
-    //Converts DirEntry into a fully processed file/directory name
-    let base = path.file_name().into_string().unwrap();
-    return base;
+    Ok(())
 }
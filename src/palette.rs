@@ -0,0 +1,298 @@
+use error::RavenError;
+use serde_json;
+use serde_json::value::{Map, Value};
+use std::{collections::BTreeMap, fs, io::Read};
+/// Number of ANSI colors in a palette
+pub const ANSI_COLORS: usize = 16;
+/// A theme's colors, stored in theme.json
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Palette {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// The 16 ANSI colors, color0 to color15. Unset colors are empty strings
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colors: Vec<String>,
+    /// Named accent colors
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub accents: BTreeMap<String, String>,
+}
+/// Checks that a color is written as #rgb, #rrggbb or #rrggbbaa
+pub fn check_color<N>(color: N) -> Result<String, RavenError>
+where
+    N: Into<String>,
+{
+    let color = color.into();
+    let hex = color.trim_start_matches('#');
+    if color.starts_with('#')
+        && [3, 6, 8].contains(&hex.len())
+        && hex.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        Ok(color)
+    } else {
+        Err(RavenError::Invalid(format!(
+            "{} is not a color. Use #rrggbb",
+            color
+        )))
+    }
+}
+/// Parses the index out of an ANSI color name like color4
+fn color_index(key: &str) -> Option<usize> {
+    if !key.starts_with("color") {
+        return None;
+    }
+    match key["color".len()..].parse::<usize>() {
+        Ok(i) if i < ANSI_COLORS => Some(i),
+        _ => None,
+    }
+}
+impl Palette {
+    /// Checks if no colors are set
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+    /// Sets a color. `key` is background, foreground, cursor, color0 to color15, or the name of
+    /// an accent
+    pub fn set<N, S>(&mut self, key: N, value: S) -> Result<(), RavenError>
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        let key = key.into();
+        let value = check_color(value)?;
+        match key.as_str() {
+            "background" => self.background = Some(value),
+            "foreground" => self.foreground = Some(value),
+            "cursor" => self.cursor = Some(value),
+            k => match color_index(k) {
+                Some(i) => {
+                    if self.colors.len() < ANSI_COLORS {
+                        self.colors.resize(ANSI_COLORS, String::new());
+                    }
+                    self.colors[i] = value;
+                }
+                None => {
+                    self.accents.insert(key, value);
+                }
+            },
+        }
+        Ok(())
+    }
+    /// Gets a color by the same names `set` takes
+    pub fn get<N>(&self, key: N) -> Option<String>
+    where
+        N: Into<String>,
+    {
+        let key = key.into();
+        match key.as_str() {
+            "background" => self.background.clone(),
+            "foreground" => self.foreground.clone(),
+            "cursor" => self.cursor.clone(),
+            k => match color_index(k) {
+                Some(i) => self.colors.get(i).filter(|x| !x.is_empty()).cloned(),
+                None => self.accents.get(k).cloned(),
+            },
+        }
+    }
+    /// All set colors with their names, in a stable order
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        for key in &["background", "foreground", "cursor"] {
+            if let Some(value) = self.get(*key) {
+                entries.push((key.to_string(), value));
+            }
+        }
+        for (i, color) in self.colors.iter().enumerate() {
+            if !color.is_empty() {
+                entries.push((format!("color{}", i), color.clone()));
+            }
+        }
+        for (name, color) in &self.accents {
+            entries.push((name.clone(), color.clone()));
+        }
+        entries
+    }
+    /// Copies all colors set in another palette into this one
+    pub fn merge(&mut self, other: &Palette) {
+        for (key, value) in other.entries() {
+            // Values were checked when they were set
+            let _ = self.set(key, value);
+        }
+    }
+    /// Returns the palette as template variables
+    pub fn to_vars(&self) -> Map<String, Value> {
+        let mut vars = Map::new();
+        for (key, value) in self.entries() {
+            vars.insert(key, Value::String(value));
+        }
+        vars
+    }
+    /// Reads a palette from a pywal colors.json or an Xresources file
+    pub fn import<N>(file: N) -> Result<Palette, RavenError>
+    where
+        N: Into<String>,
+    {
+        let mut contents = String::new();
+        fs::File::open(file.into())?.read_to_string(&mut contents)?;
+        if contents.trim_start().starts_with('{') {
+            Palette::from_pywal(contents)
+        } else {
+            Palette::from_xresources(contents)
+        }
+    }
+    /// Parses pywal's colors.json layout
+    pub fn from_pywal<N>(json: N) -> Result<Palette, RavenError>
+    where
+        N: Into<String>,
+    {
+        let json: Value = serde_json::from_str(&json.into())?;
+        let mut palette = Palette::default();
+        for section in &["special", "colors"] {
+            if let Some(colors) = json.get(*section).and_then(|x| x.as_object()) {
+                for (key, value) in colors {
+                    if let Some(value) = value.as_str() {
+                        palette.set(key.as_str(), value)?;
+                    }
+                }
+            }
+        }
+        Ok(palette)
+    }
+    /// Parses color definitions like `*.background: #1d1f21` or `URxvt*color1: #cc6666` out of
+    /// Xresources. Other lines are ignored
+    pub fn from_xresources<N>(text: N) -> Result<Palette, RavenError>
+    where
+        N: Into<String>,
+    {
+        let mut palette = Palette::default();
+        for line in text.into().lines() {
+            let line = line.trim();
+            if line.starts_with('!') || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, ':');
+            let (res, value) = match (parts.next(), parts.next()) {
+                (Some(res), Some(value)) => (res.trim(), value.trim()),
+                _ => continue,
+            };
+            let key = res.rsplit(|c| c == '.' || c == '*').next().unwrap_or(res);
+            let key = if key == "cursorColor" { "cursor" } else { key };
+            let known =
+                ["background", "foreground", "cursor"].contains(&key) || color_index(key).is_some();
+            if known && check_color(value).is_ok() {
+                palette.set(key, value)?;
+            }
+        }
+        Ok(palette)
+    }
+}
+/// File formats the palette can be exported to, written as colors.[format]
+pub const EXPORT_FORMATS: [&str; 6] = ["sh", "json", "css", "scss", "Xresources", "rasi"];
+/// Turns a color name into something usable as a variable name
+fn ident<N>(name: N) -> String
+where
+    N: Into<String>,
+{
+    name.into()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+/// Quotes a value for a POSIX shell, so paths containing `'` survive being sourced
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+impl Palette {
+    /// Renders the palette in one of the export formats. `wallpaper` is the path of the theme's
+    /// wallpaper, if it has one
+    pub fn export<N>(&self, format: N, wallpaper: Option<&str>) -> Result<String, RavenError>
+    where
+        N: Into<String>,
+    {
+        let format = format.into();
+        let entries = self.entries();
+        let mut out = String::new();
+        match format.as_str() {
+            "sh" => {
+                if let Some(wallpaper) = wallpaper {
+                    out += &format!("wallpaper={}\n", sh_quote(wallpaper));
+                }
+                for (k, v) in entries {
+                    out += &format!("{}={}\n", ident(k), sh_quote(&v));
+                }
+            }
+            "json" => {
+                let mut special = Map::new();
+                let mut colors = Map::new();
+                for (k, v) in entries {
+                    if color_index(&k).is_some() {
+                        colors.insert(k, Value::String(v));
+                    } else if ["background", "foreground", "cursor"].contains(&k.as_str()) {
+                        special.insert(k, Value::String(v));
+                    }
+                }
+                let mut json = Map::new();
+                json.insert(
+                    "wallpaper".to_string(),
+                    Value::String(wallpaper.unwrap_or("").to_string()),
+                );
+                json.insert("alpha".to_string(), Value::String("100".to_string()));
+                json.insert("special".to_string(), Value::Object(special));
+                json.insert("colors".to_string(), Value::Object(colors));
+                out = serde_json::to_string_pretty(&json)? + "\n";
+            }
+            "css" => {
+                out += ":root {\n";
+                for (k, v) in entries {
+                    out += &format!("    --{}: {};\n", ident(k), v);
+                }
+                out += "}\n";
+            }
+            "scss" => {
+                for (k, v) in entries {
+                    out += &format!("${}: {};\n", ident(k), v);
+                }
+            }
+            "Xresources" => {
+                for (k, v) in entries {
+                    if k == "cursor" {
+                        out += &format!("*.cursorColor: {}\n", v);
+                    } else if k == "background" || k == "foreground" || color_index(&k).is_some() {
+                        out += &format!("*.{}: {}\n", k, v);
+                    }
+                }
+            }
+            "rasi" => {
+                out += "* {\n";
+                for (k, v) in entries {
+                    out += &format!("    {}: {};\n", ident(k), v);
+                }
+                out += "}\n";
+            }
+            _ => {
+                return Err(RavenError::Invalid(format!(
+                    "Unknown palette export format {}",
+                    format
+                )))
+            }
+        }
+        Ok(out)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn sh_export_escapes_quotes() {
+        let mut palette = Palette::default();
+        palette.set("background", "#000000").unwrap();
+        let out = palette
+            .export("sh", Some("/home/me/it's here.png"))
+            .unwrap();
+        assert!(out.contains("wallpaper='/home/me/it'\\''s here.png'\n"));
+        assert!(out.contains("background='#000000'\n"));
+    }
+}
@@ -0,0 +1,175 @@
+use dirs::home_dir;
+use error::RavenError;
+use std::env;
+#[cfg(test)]
+use std::{fs, process};
+/// Locations raven reads themes and config from, and writes loaded options to
+#[derive(Clone, Debug)]
+pub struct RavenPaths {
+    /// The user's home directory
+    pub home: String,
+    /// Base directory for program configs, `$XDG_CONFIG_HOME` or `~/.config`
+    pub config: String,
+    /// Raven's own directory, holding config.json and the themes
+    pub root: String,
+    /// Raven's cache directory, `$XDG_CACHE_HOME/raven` or `~/.cache/raven`
+    pub cache: String,
+    /// Where ravend keeps its pidfile and socket, and raven the processes it started,
+    /// `$XDG_RUNTIME_DIR/raven` or the cache directory
+    pub runtime: String,
+}
+impl RavenPaths {
+    /// Resolves paths from the environment. The raven root is, in order of preference, `root`,
+    /// `$RAVEN_HOME`, `$XDG_CONFIG_HOME/raven` and `~/.config/raven`
+    pub fn new(root: Option<String>) -> Result<RavenPaths, RavenError> {
+        let home = home_dir()
+            .and_then(|x| x.to_str().map(String::from))
+            .ok_or_else(|| RavenError::Invalid("Couldn't find home directory".to_string()))?;
+        let config = match env::var("XDG_CONFIG_HOME") {
+            Ok(ref config) if !config.is_empty() => config.clone(),
+            _ => home.clone() + "/.config",
+        };
+        let root = match root.or_else(|| env::var("RAVEN_HOME").ok()) {
+            Some(ref root) if !root.is_empty() => root.clone(),
+            _ => config.clone() + "/raven",
+        };
+        let cache = match env::var("XDG_CACHE_HOME") {
+            Ok(ref cache) if !cache.is_empty() => cache.clone() + "/raven",
+            _ => home.clone() + "/.cache/raven",
+        };
+        let runtime = match env::var("XDG_RUNTIME_DIR") {
+            Ok(ref runtime) if !runtime.is_empty() => runtime.clone() + "/raven",
+            _ => cache.clone(),
+        };
+        Ok(RavenPaths {
+            home,
+            config,
+            root,
+            cache,
+            runtime,
+        })
+    }
+    /// Path to the main config file
+    pub fn config_file(&self) -> String {
+        self.root.clone() + "/config.json"
+    }
+    /// Path to the themes directory
+    pub fn themes(&self) -> String {
+        self.root.clone() + "/themes"
+    }
+    /// Path to a theme's directory
+    pub fn theme<N>(&self, name: N) -> String
+    where
+        N: Into<String>,
+    {
+        self.themes() + "/" + &name.into()
+    }
+    /// Path to a file within a theme's directory
+    pub fn theme_file<N, S>(&self, name: N, file: S) -> String
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        self.theme(name) + "/" + &file.into()
+    }
+    /// Path to one of a variant's option files, within its theme's directory
+    pub fn variant_file<N, S, T>(&self, name: N, variant: S, file: T) -> String
+    where
+        N: Into<String>,
+        S: Into<String>,
+        T: Into<String>,
+    {
+        self.theme_file(
+            name,
+            String::from("variants/") + &variant.into() + "/" + &file.into(),
+        )
+    }
+    /// Path to a file within the raven root, such as base_i3
+    pub fn root_file<N>(&self, file: N) -> String
+    where
+        N: Into<String>,
+    {
+        self.root.clone() + "/" + &file.into()
+    }
+    /// Path to the file holding the default template variables
+    pub fn variables(&self) -> String {
+        self.root_file("variables.json")
+    }
+    /// Path to the file listing the processes raven started. Kept in the runtime directory, which
+    /// is cleared at logout, since the PIDs mean nothing after that
+    pub fn pids(&self) -> String {
+        self.runtime.clone() + "/pids.json"
+    }
+    /// Path to the file marking that raven has tracked the processes it starts. Kept in the raven
+    /// root, so that programs are only stopped by name before raven ever tracked them, and not
+    /// after each logout clears the runtime directory
+    pub fn pids_marker(&self) -> String {
+        self.root_file("pids.tracked")
+    }
+    /// Path to the socket ravend listens for commands on
+    pub fn socket(&self) -> String {
+        self.runtime.clone() + "/ravend.sock"
+    }
+    /// Path to the file holding ravend's PID, locked while it runs
+    pub fn pidfile(&self) -> String {
+        self.runtime.clone() + "/ravend.pid"
+    }
+    /// Path to the directory holding the original versions of files raven overwrote
+    pub fn backup(&self) -> String {
+        self.root_file("backup")
+    }
+    /// Path to a file within the backup directory
+    pub fn backup_file<N>(&self, file: N) -> String
+    where
+        N: Into<String>,
+    {
+        self.backup() + "/" + &file.into()
+    }
+    /// Path to a file within the cache directory
+    pub fn cache_file<N>(&self, file: N) -> String
+    where
+        N: Into<String>,
+    {
+        self.cache.clone() + "/" + &file.into()
+    }
+    /// Expands a leading `~` in a path to the home directory
+    pub fn expand<N>(&self, path: N) -> String
+    where
+        N: Into<String>,
+    {
+        let path = path.into();
+        if path == "~" {
+            self.home.clone()
+        } else if path.starts_with("~/") {
+            self.home.clone() + &path[1..]
+        } else {
+            path
+        }
+    }
+    /// Path to a program's config file or directory, relative to the config base directory
+    pub fn target<N>(&self, file: N) -> String
+    where
+        N: Into<String>,
+    {
+        self.config.clone() + "/" + &file.into()
+    }
+}
+#[cfg(test)]
+impl RavenPaths {
+    /// Paths within a new, empty directory under the system's temporary directory, for tests
+    pub fn temp(name: &str) -> RavenPaths {
+        let home = env::temp_dir()
+            .join(format!("raven-test-{}-{}", name, process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(&home).expect("couldn't create test directory");
+        RavenPaths {
+            config: home.clone() + "/.config",
+            root: home.clone() + "/.config/raven",
+            cache: home.clone() + "/.cache/raven",
+            runtime: home.clone() + "/run",
+            home,
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use error::RavenError;
+use paths::RavenPaths;
+use runner::Cmd;
+use serde_json;
+use std::{fs, io};
+/// A background process raven started while loading a theme
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Spawned {
+    /// The option that started it
+    pub option: String,
+    /// Its PID, which is also its process group, since raven starts each in its own group
+    pub pid: u32,
+    /// The command, for showing to the user
+    pub command: String,
+    /// When it started, in clock ticks since boot, to tell it apart from a later process that was
+    /// given the same PID. None if it couldn't be read
+    #[serde(default)]
+    pub started: Option<u64>,
+}
+impl Spawned {
+    pub fn new<N, S>(option: N, pid: u32, command: S) -> Spawned
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        Spawned {
+            option: option.into(),
+            pid,
+            command: command.into(),
+            started: start_time(pid),
+        }
+    }
+    /// Whether the process raven started is still running. A process with the same PID that
+    /// started at another time isn't it, and neither is any process if the start time wasn't
+    /// recorded
+    pub fn alive(&self) -> bool {
+        self.started.is_some() && start_time(self.pid) == self.started
+    }
+    /// The command that stops the process and anything it started
+    pub fn stop_command(&self) -> Cmd {
+        Cmd::new("kill")
+            .arg("-TERM")
+            .arg("--")
+            .arg(format!("-{}", self.pid))
+    }
+}
+/// When a process started, in clock ticks since boot, from `/proc/<pid>/stat`. None if there's no
+/// such process
+pub fn start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name comes second, in parentheses, and may itself contain spaces or parentheses,
+    // so fields are counted from the last closing one. The start time is the 22nd field
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().nth(19)?.parse().ok()
+}
+/// Reads the processes started by previous loads. None if raven has never recorded any, as
+/// before it tracked them. Empty if it has, but none were recorded this session
+pub fn load_spawned(paths: &RavenPaths) -> Result<Option<Vec<Spawned>>, RavenError> {
+    match fs::read_to_string(paths.pids()) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            if fs::metadata(paths.pids_marker()).is_ok() {
+                Ok(Some(Vec::new()))
+            } else {
+                Ok(None)
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+/// Replaces the list of processes started by raven. Processes that have exited are left out, so
+/// the list doesn't keep growing and their PIDs aren't mistaken for ones raven started
+pub fn save_spawned(paths: &RavenPaths, spawned: &[Spawned]) -> Result<(), RavenError> {
+    let alive = spawned
+        .iter()
+        .filter(|x| x.alive())
+        .cloned()
+        .collect::<Vec<Spawned>>();
+    fs::create_dir_all(&paths.runtime)?;
+    let tmp = paths.runtime.clone() + "/~pids.json";
+    fs::write(&tmp, serde_json::to_string_pretty(&alive)?)?;
+    fs::rename(tmp, paths.pids())?;
+    if fs::metadata(paths.pids_marker()).is_err() {
+        fs::write(paths.pids_marker(), "")?;
+    }
+    Ok(())
+}
+/// Adds processes to the list of processes started by raven
+pub fn record_spawned(paths: &RavenPaths, new: &[Spawned]) -> Result<(), RavenError> {
+    let mut spawned = load_spawned(paths)?.unwrap_or_default();
+    spawned.extend(new.iter().cloned());
+    save_spawned(paths, &spawned)
+}
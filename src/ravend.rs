@@ -1,60 +1,475 @@
-use std::fs;
-extern crate time;
-use std::env;
-use std::fs::DirEntry;
-use std::io;
-use std::io::Read;
-use std::time::Duration;
-
-use std::process::Command;
-use std::thread;
+extern crate libc;
+extern crate ravenlib;
+extern crate serde_json;
+use ravenlib::{
+    config::*,
+    cycle::{playlist_themes, CycleStatus, Position, Request, Response},
+    daemon::lock_pidfile,
+    error::RavenError,
+    paths::RavenPaths,
+    rules::{matching_rule, sysfs_root, Facts, Rule},
+    runner::SystemRunner,
+    themes::*,
+};
+use std::{
+    env, fs, io,
+    io::{BufRead, BufReader, Write},
+    mem,
+    os::unix::net::{UnixListener, UnixStream},
+    process::exit,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+/// How long a client of the control socket gets to send its request or read the answer, so one
+/// that never does doesn't hold up everyone after it
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Something for the cycle to act on
+enum Event {
+    /// A request from the control socket, with where to send the answer
+    Request(Request, mpsc::Sender<Response>),
+    /// SIGTERM or SIGINT: clean up and exit
+    Terminate,
+    /// SIGHUP: read the time file again, and check the rules
+    Reload,
+}
 fn main() {
-    if fs::metadata(get_home() + "/.config/raven/time").is_err() {
-        println!("There is no time configured for ravend. Edit ~/.config/raven/time with a number of seconds.");
-    } else {
-        let mut time = String::new();
-        fs::File::open(get_home() + "/.config/raven/time")
-            .unwrap()
-            .read_to_string(&mut time)
-            .unwrap();
-        time = String::from(time.trim());
-        let time_r = time.parse::<i32>();
-        if time_r.is_ok() {
-            let time_t = time_r.unwrap();
-            let themes = fs::read_dir(get_home() + "/.config/raven/themes")
-                .expect("Couldn't read themes")
-                .collect::<Vec<io::Result<DirEntry>>>()
-                .into_iter()
-                .map(|x| proc_path(x.unwrap()))
-                .collect::<Vec<String>>();
-            start_cycle(themes, time_t);
-        } else {
-            println!("Time file does not contain a number in seconds. {}", time);
-        }
-    }
-}
-fn get_home() -> String {
-    return String::from(env::home_dir().unwrap().to_str().unwrap());
-}
-fn start_cycle(entries: Vec<String>, time: i32) {
-    let mut index = 0;
+    let paths = match RavenPaths::new(None) {
+        Ok(paths) => paths,
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
+    // Held until ravend exits
+    let _pidfile = match lock_pidfile(&paths) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    };
+    let playlist = env::args().nth(1);
+    let (interval, listener, signals) = match read_playlist(&paths, playlist.as_ref())
+        .and_then(|_| read_interval(&paths))
+        .and_then(|x| Ok((x, bind(&paths)?, block_signals()?)))
+    {
+        Ok(res) => res,
+        Err(e) => {
+            println!("{}", e);
+            let _ = fs::remove_file(paths.pidfile());
+            exit(1);
+        }
+    };
+    let (tx, rx) = mpsc::channel();
+    let signal_tx = tx.clone();
+    thread::spawn(move || wait_for_signals(signals, signal_tx));
+    thread::spawn(move || serve(listener, tx));
+    Cycler::new(paths, playlist, interval).run(rx);
+}
+/// Blocks the signals ravend handles, so they are only received by `wait_for_signals`. Must be
+/// called before any other thread is started, as threads inherit the signal mask
+fn block_signals() -> Result<libc::sigset_t, RavenError> {
+    unsafe {
+        let mut signals: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        for &signal in &[libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+            libc::sigaddset(&mut signals, signal);
+        }
+        match libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) {
+            0 => Ok(signals),
+            e => Err(io::Error::from_raw_os_error(e).into()),
+        }
+    }
+}
+/// Turns the blocked signals into events for the cycle, as they arrive
+fn wait_for_signals(signals: libc::sigset_t, tx: mpsc::Sender<Event>) {
     loop {
-        let len = entries.len();
-        if index >= len {
-            index = 0;
-        }
-        Command::new("sh")
-            .arg("-c")
-            .arg(String::from("raven load ") + &entries[index] + " &")
-            .spawn()
-            .expect("Failed to swap.");
-        thread::sleep(Duration::from_secs(time as u64));
-        println!("Changing theme!");
-        index = index + 1;
-    }
-}
-fn proc_path(path: DirEntry) -> String {
-    //Converts DirEntry into a fully processed file/directory name
-    let base = path.file_name().into_string().unwrap();
-    return base;
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            continue;
+        }
+        let event = match signal {
+            libc::SIGHUP => Event::Reload,
+            _ => Event::Terminate,
+        };
+        if tx.send(event).is_err() {
+            return;
+        }
+    }
+}
+/// Reads a playlist from the config, or gives one with every theme when there is no name
+fn read_playlist(paths: &RavenPaths, name: Option<&String>) -> Result<Playlist, RavenError> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(Playlist::default()),
+    };
+    match get_config(paths)?.playlists.remove(name) {
+        Some(playlist) => Ok(playlist),
+        None => Err(RavenError::Invalid(format!(
+            "There is no playlist named {}.",
+            name
+        ))),
+    }
+}
+/// Reads the number of seconds between themes from the time file in the raven root
+fn read_interval(paths: &RavenPaths) -> Result<Duration, RavenError> {
+    let time = match fs::read_to_string(paths.root_file("time")) {
+        Ok(time) => time,
+        Err(_) => {
+            return Err(RavenError::Invalid(format!(
+                "There is no time configured for ravend. Edit {} with a number of seconds.",
+                paths.root_file("time")
+            )));
+        }
+    };
+    match time.trim().parse::<u64>() {
+        Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+        _ => Err(RavenError::Invalid(format!(
+            "Time file does not contain a number in seconds. {}",
+            time.trim()
+        ))),
+    }
+}
+/// Listens on the control socket, replacing the socket a previous ravend left behind. The
+/// pidfile lock makes sure that ravend isn't still running
+fn bind(paths: &RavenPaths) -> Result<UnixListener, RavenError> {
+    let socket = paths.socket();
+    let _ = fs::remove_file(&socket);
+    Ok(UnixListener::bind(&socket)?)
+}
+/// Passes the requests sent to the control socket on to the cycle, one connection at a time
+fn serve(listener: UnixListener, tx: mpsc::Sender<Event>) {
+    for stream in listener.incoming() {
+        if let Err(e) = stream
+            .map_err(RavenError::from)
+            .and_then(|x| answer(x, &tx))
+        {
+            println!("Couldn't answer a command: {}", e);
+        }
+    }
+}
+/// Reads one request from a connection and writes back the cycle's response
+fn answer(stream: UnixStream, tx: &mpsc::Sender<Event>) -> Result<(), RavenError> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    if line.is_empty() {
+        // Only checking whether ravend is running
+        return Ok(());
+    }
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => {
+            let (reply, response) = mpsc::channel();
+            tx.send(Event::Request(request, reply))
+                .ok()
+                .and_then(|_| response.recv().ok())
+                .unwrap_or_else(|| Response::Error("Cycle daemon is stopping.".to_string()))
+        }
+        Err(e) => Response::Error(format!("Invalid command: {}", e)),
+    };
+    (&stream).write_all((serde_json::to_string(&response)? + "\n").as_bytes())?;
+    Ok(())
+}
+/// Loads a theme in place of the previous one, returning the options that failed
+fn cycle_to(paths: &RavenPaths, theme: &str) -> Result<Vec<String>, RavenError> {
+    let runner = SystemRunner;
+    clear_prev(paths, &Selection::default(), &runner)?;
+    let report = run_theme(load_theme(paths, theme)?, &runner)?;
+    Ok(report.failed_options())
+}
+/// Rounds up to whole seconds, so a switch that is due soon isn't shown as 0 seconds away
+fn whole_secs(duration: Duration) -> u64 {
+    duration.as_secs() + if duration.subsec_nanos() > 0 { 1 } else { 0 }
+}
+/// Which theme of the playlist to switch to
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    /// The one the position is at, as when starting, or the next one if it left the playlist
+    Stay,
+    Next,
+    Prev,
+}
+/// Goes through the themes of a playlist in turn, unless a rule says which theme to load. The
+/// playlist, its themes and the rules are read again every time, so changes to them are picked up
+struct Cycler {
+    paths: RavenPaths,
+    position: Position,
+    /// The theme loaded last, by the playlist or a rule
+    theme: Option<String>,
+    /// Name of the rule that matched at the last check
+    rule: Option<String>,
+    /// Seconds between checks of the rules, from the config
+    rules_interval: Duration,
+    /// When the rules are checked next
+    rules_deadline: Instant,
+    /// Seconds between themes, from the time file
+    interval: Duration,
+    /// How long the current theme stays instead, if the playlist says
+    theme_duration: Option<Duration>,
+    /// When the next theme is due
+    deadline: Instant,
+    /// Time that was left until the next theme when paused
+    paused: Option<Duration>,
+}
+impl Cycler {
+    fn new(paths: RavenPaths, playlist: Option<String>, interval: Duration) -> Cycler {
+        let position = Position::load(&paths, playlist.as_deref());
+        Cycler {
+            paths,
+            position,
+            theme: None,
+            rule: None,
+            rules_interval: Duration::from_secs(default_rules_interval()),
+            rules_deadline: Instant::now(),
+            interval,
+            theme_duration: None,
+            deadline: Instant::now(),
+            paused: None,
+        }
+    }
+    /// Loads the theme of the matching rule, or the one a previous ravend left off at. Then
+    /// switches themes when they are due and answers requests in between
+    fn run(mut self, rx: mpsc::Receiver<Event>) {
+        self.check_rules();
+        if self.rule.is_none() {
+            self.log_switch(Step::Stay);
+        }
+        loop {
+            let message = match self.wake_at() {
+                Some(at) => rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+                None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+            };
+            match message {
+                Ok(Event::Request(request, reply)) => {
+                    let _ = reply.send(self.handle(request));
+                }
+                Ok(Event::Terminate) => self.shut_down(),
+                Ok(Event::Reload) => self.reload(),
+                Err(mpsc::RecvTimeoutError::Timeout) => self.tick(),
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    // The control socket is gone, so keep cycling without it
+                    let at = self.wake_at().unwrap_or(self.deadline);
+                    thread::sleep(at.saturating_duration_since(Instant::now()));
+                    self.tick();
+                }
+            }
+        }
+    }
+    /// When the next theme or the next check of the rules is due. While a rule matches, only the
+    /// check is. None while paused
+    fn wake_at(&self) -> Option<Instant> {
+        match (self.paused, self.rule.as_ref()) {
+            (Some(_), _) => None,
+            (None, Some(_)) => Some(self.rules_deadline),
+            (None, None) => Some(self.deadline.min(self.rules_deadline)),
+        }
+    }
+    /// Checks the rules and switches themes, if they are due
+    fn tick(&mut self) {
+        if Instant::now() >= self.rules_deadline {
+            self.check_rules();
+        }
+        if self.rule.is_none() && Instant::now() >= self.deadline {
+            self.log_switch(Step::Next);
+        }
+    }
+    /// Checks the rules. When the matching rule changes, its theme is loaded, or the playlist's
+    /// theme again if none matches any more
+    fn check_rules(&mut self) {
+        let rule = match self.match_rule() {
+            Ok(rule) => rule,
+            Err(e) => {
+                println!("Couldn't check the rules: {}", e);
+                return;
+            }
+        };
+        if rule.as_ref().map(|x| &x.name) == self.rule.as_ref() {
+            return;
+        }
+        self.rule = rule.as_ref().map(|x| x.name.clone());
+        match rule {
+            Some(rule) => {
+                println!("Rule {} matches", rule.name);
+                if let Err(e) = self.load(rule.theme) {
+                    println!("{}", e);
+                }
+            }
+            None => {
+                println!("No rule matches any more");
+                self.log_switch(Step::Stay);
+            }
+        }
+    }
+    /// The first rule that matches now. Schedules the next check
+    fn match_rule(&mut self) -> Result<Option<Rule>, RavenError> {
+        // Kept if the config can't be read
+        self.rules_deadline = Instant::now() + self.rules_interval;
+        let conf = get_config(&self.paths)?;
+        self.rules_interval = Duration::from_secs(conf.rules_interval.max(1));
+        self.rules_deadline = Instant::now() + self.rules_interval;
+        if conf.rules.is_empty() {
+            return Ok(None);
+        }
+        let facts = Facts::gather(
+            &conf.rules,
+            &conf.outputs_command,
+            &sysfs_root(),
+            &SystemRunner,
+        )?;
+        Ok(matching_rule(&self.paths, &conf.rules, &facts)?.cloned())
+    }
+    /// How long the current theme stays loaded
+    fn duration(&self) -> Duration {
+        self.theme_duration.unwrap_or(self.interval)
+    }
+    fn playlist(&self) -> Result<Playlist, RavenError> {
+        read_playlist(&self.paths, self.position.playlist.as_ref())
+    }
+    /// Switches themes, printing what went wrong as there is nobody to answer
+    fn log_switch(&mut self, step: Step) {
+        if let Err(e) = self.switch(step) {
+            println!("{}", e);
+        }
+    }
+    /// Loads another theme of the playlist and saves the position. Restarts the wait for the
+    /// next switch, even if no theme could be loaded
+    fn switch(&mut self, step: Step) -> Result<(), RavenError> {
+        let res = self.step(step);
+        self.deadline = Instant::now() + self.duration();
+        if self.paused.is_some() {
+            self.paused = Some(self.duration());
+        }
+        res
+    }
+    fn step(&mut self, step: Step) -> Result<(), RavenError> {
+        let playlist = self.playlist()?;
+        let themes = playlist_themes(&self.paths, &playlist)?;
+        let theme = match step {
+            Step::Stay => match self.position.current() {
+                Some(current) if themes.iter().any(|x| x == current) => Some(current.to_string()),
+                _ => self.position.next(&playlist, &themes),
+            },
+            Step::Next => self.position.next(&playlist, &themes),
+            Step::Prev => self.position.prev(&playlist, &themes),
+        };
+        let theme = match theme {
+            Some(theme) => theme,
+            None if themes.is_empty() => {
+                return Err(RavenError::Invalid(
+                    "There are no themes to cycle through.".to_string(),
+                ));
+            }
+            None if step == Step::Prev => {
+                return Err(RavenError::Invalid(
+                    "There is no earlier theme to go back to.".to_string(),
+                ));
+            }
+            None => {
+                return Err(RavenError::Invalid(
+                    "There is no theme with a weight above 0 to switch to.".to_string(),
+                ));
+            }
+        };
+        if let Err(e) = self.position.save(&self.paths) {
+            println!("Couldn't save the position in the playlist: {}", e);
+        }
+        self.theme_duration = match playlist.durations.get(&theme) {
+            Some(&secs) if secs > 0 => Some(Duration::from_secs(secs)),
+            _ => None,
+        };
+        self.load(theme)
+    }
+    /// Loads a theme in place of the previous one
+    fn load(&mut self, theme: String) -> Result<(), RavenError> {
+        println!("Changing theme to {}", theme);
+        self.theme = Some(theme.clone());
+        match cycle_to(&self.paths, &theme) {
+            Ok(ref failed) if failed.is_empty() => Ok(()),
+            Ok(failed) => Err(RavenError::Invalid(format!(
+                "Some options of {} failed to load: {}",
+                theme,
+                failed.join(", ")
+            ))),
+            Err(e) => Err(RavenError::Invalid(format!(
+                "Couldn't load {}: {}",
+                theme, e
+            ))),
+        }
+    }
+    fn handle(&mut self, request: Request) -> Response {
+        match request {
+            Request::Next | Request::Prev => {
+                let step = if request == Request::Next {
+                    Step::Next
+                } else {
+                    Step::Prev
+                };
+                if let Err(e) = self.switch(step) {
+                    return Response::Error(e.to_string());
+                }
+            }
+            Request::Pause => {
+                if self.paused.is_none() {
+                    self.paused = Some(self.deadline.saturating_duration_since(Instant::now()));
+                }
+            }
+            Request::Resume => {
+                if let Some(remaining) = self.paused.take() {
+                    self.deadline = Instant::now() + remaining;
+                }
+            }
+            Request::Status => {}
+            Request::SetInterval { seconds: 0 } => {
+                return Response::Error("The interval must be at least one second.".to_string());
+            }
+            Request::SetInterval { seconds } => {
+                if let Err(e) = fs::write(self.paths.root_file("time"), seconds.to_string()) {
+                    return Response::Error(format!("Couldn't save the interval: {}", e));
+                }
+                self.interval = Duration::from_secs(seconds);
+                self.deadline = Instant::now() + self.duration();
+                if self.paused.is_some() {
+                    self.paused = Some(self.duration());
+                }
+            }
+        }
+        Response::Status(self.status())
+    }
+    /// Removes the socket and pidfile and exits. The theme that is loaded stays loaded
+    fn shut_down(&self) -> ! {
+        println!("Stopping");
+        let _ = fs::remove_file(self.paths.socket());
+        let _ = fs::remove_file(self.paths.pidfile());
+        exit(0);
+    }
+    /// Reads the time file again and checks the rules right away. A shorter interval brings the
+    /// next switch forward, so it is never further away than the new interval
+    fn reload(&mut self) {
+        match read_interval(&self.paths) {
+            Ok(interval) => {
+                println!("Reloaded, switching every {} seconds", interval.as_secs());
+                self.interval = interval;
+                let duration = self.duration();
+                self.deadline = self.deadline.min(Instant::now() + duration);
+                self.paused = self.paused.map(|x| x.min(duration));
+            }
+            Err(e) => println!("Couldn't reload, keeping the interval: {}", e),
+        }
+        self.rules_deadline = Instant::now();
+    }
+    fn status(&self) -> CycleStatus {
+        let remaining = self
+            .paused
+            .unwrap_or_else(|| self.deadline.saturating_duration_since(Instant::now()));
+        CycleStatus {
+            playlist: self.position.playlist.clone(),
+            theme: self.theme.clone(),
+            rule: self.rule.clone(),
+            paused: self.paused.is_some(),
+            interval: self.interval.as_secs(),
+            remaining: whole_secs(remaining),
+        }
+    }
 }
@@ -2,23 +2,56 @@ extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
 extern crate dirs;
+extern crate image;
+extern crate libc;
 extern crate multipart;
+extern crate rand;
 extern crate reqwest;
 extern crate tar;
+extern crate time;
+/// Backups of the files raven overwrites
+pub mod backup;
+/// Creating themes from the running desktop
+pub mod capture;
+/// Playlists ravend cycles through, and the protocol for controlling it over its socket
+pub mod cycle;
+/// Diffs of the files a theme would change
+pub mod diff;
+/// Error type returned by ravenlib
+pub mod error;
+/// Palette generation from wallpaper images
+pub mod extract;
+/// Option loaders that apply a theme's options
+pub mod loaders;
+/// Theme color palettes
+pub mod palette;
+/// Locations of raven's files
+pub mod paths;
+/// Tracking of the processes raven starts
+pub mod procs;
 pub mod ravenserver;
+/// Results of loading a theme
+pub mod report;
+/// Rules for switching themes automatically
+pub mod rules;
+/// Running external commands
+pub mod runner;
+/// Rendering of templated option files
+pub mod template;
+/// Staging and applying the changes made by loading a theme
+pub mod transaction;
 use std::fs::DirEntry;
 /// Module for theme manipulation
 pub mod themes;
 /// Config module
 pub mod config {
+    use crate::error::RavenError;
+    use crate::palette::{Palette, EXPORT_FORMATS};
+    use crate::paths::RavenPaths;
+    use crate::rules::Rule;
     use crate::themes::*;
-    use dirs::home_dir;
-    use std::{fs, fs::OpenOptions, io::Read, io::Write};
     use serde_json::value::Map;
-    /// Returns home directory as string
-    pub fn get_home() -> String {
-        return String::from(home_dir().unwrap().to_str().unwrap());
-    }
+    use std::{collections::HashMap, fs, fs::OpenOptions, io, io::Read, io::Write};
     /// Default ravenserver host
     pub fn default_host() -> String {
         String::from("https://demenses.net")
@@ -27,10 +60,97 @@ pub mod config {
     pub fn default_screen() -> String {
         String::new()
     }
+    /// Default palette export formats
+    pub fn default_exports() -> Vec<String> {
+        EXPORT_FORMATS.iter().map(|x| x.to_string()).collect()
+    }
+    /// Default options whose processes are stopped when another theme is loaded
+    pub fn default_stop() -> Vec<String> {
+        vec![
+            "poly".to_string(),
+            "lemonbar".to_string(),
+            "dunst".to_string(),
+        ]
+    }
+    /// Default number of seconds between checks of the rules
+    pub fn default_rules_interval() -> u64 {
+        60
+    }
+    /// Default command that prints the number of connected outputs
+    pub fn default_outputs_command() -> String {
+        String::from("xrandr --query | grep -c ' connected'")
+    }
     /// Default raven theme description
     pub fn default_desc() -> String {
         String::from("A raven theme.")
     }
+    /// How a custom option's file is put into place
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum CustomMode {
+        /// Copy the theme's file over the destination
+        Copy,
+        /// Append the theme's file to base_[name] in the raven directory, like i3 and base_i3
+        Append,
+        /// Symlink the destination to the theme's file
+        Symlink,
+    }
+    impl Default for CustomMode {
+        fn default() -> CustomMode {
+            CustomMode::Copy
+        }
+    }
+    /// A user-defined option type, for programs raven doesn't know about
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct CustomOption {
+        /// Name of the option, as used with `raven add`
+        pub name: String,
+        /// Where the option's file goes. A leading ~ is expanded to the home directory
+        pub path: String,
+        #[serde(default)]
+        pub mode: CustomMode,
+        /// Shell command run after the file is in place
+        #[serde(default)]
+        pub reload: Option<String>,
+    }
+    /// Order ravend goes through a playlist in
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum PlaylistOrder {
+        /// One after another, starting over at the end
+        Sequential,
+        /// All themes in a random order, then again in a new one
+        Shuffle,
+        /// A random theme each time, picked by weight
+        Weighted,
+    }
+    impl Default for PlaylistOrder {
+        fn default() -> PlaylistOrder {
+            PlaylistOrder::Sequential
+        }
+    }
+    /// A named selection of themes for ravend to cycle through
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct Playlist {
+        /// Themes to cycle through, in this order
+        #[serde(default)]
+        pub themes: Vec<String>,
+        /// Tags whose themes are cycled through as well, in name order. With neither themes nor
+        /// tags, every theme is
+        #[serde(default)]
+        pub tags: Vec<String>,
+        /// Themes left out, even if listed or tagged
+        #[serde(default)]
+        pub exclude: Vec<String>,
+        #[serde(default)]
+        pub order: PlaylistOrder,
+        /// How likely each theme is to be picked in weighted order. Themes not listed weigh 1
+        #[serde(default)]
+        pub weights: HashMap<String, u32>,
+        /// Seconds each theme stays loaded, for themes that don't use the time file's interval
+        #[serde(default)]
+        pub durations: HashMap<String, u64>,
+    }
     /// Config structure for holding all main config options
     #[derive(Serialize, Deserialize, Debug)]
     pub struct Config {
@@ -38,9 +158,33 @@ pub mod config {
         pub polybar: Vec<String>,
         pub menu_command: String,
         pub last: String,
+        /// Variant of the last loaded theme, or empty if it was loaded without one
+        #[serde(default)]
+        pub last_variant: String,
         pub editing: String,
         #[serde(default = "default_host")]
         pub host: String,
+        #[serde(default)]
+        pub custom: Vec<CustomOption>,
+        /// Formats the palette of a loaded theme is written to the cache directory in
+        #[serde(default = "default_exports")]
+        pub palette_exports: Vec<String>,
+        /// Options whose background processes, started by the last loaded theme, are stopped
+        /// before loading a theme
+        #[serde(default = "default_stop")]
+        pub stop: Vec<String>,
+        /// Playlists ravend can be started with
+        #[serde(default)]
+        pub playlists: HashMap<String, Playlist>,
+        /// Rules for ravend to switch themes by. The first one that matches wins
+        #[serde(default)]
+        pub rules: Vec<Rule>,
+        /// Seconds between ravend's checks of the rules
+        #[serde(default = "default_rules_interval")]
+        pub rules_interval: u64,
+        /// Shell command printing the number of connected outputs, for rules about outputs
+        #[serde(default = "default_outputs_command")]
+        pub outputs_command: String,
     }
     impl Config {
         /// Default method for config file
@@ -50,199 +194,355 @@ pub mod config {
                 polybar: vec!["main".to_string(), "other".to_string()],
                 menu_command: "rofi -theme sidebar -mesg 'raven:' -p '> ' -dmenu".to_string(),
                 last: "".to_string(),
+                last_variant: "".to_string(),
                 editing: "".to_string(),
                 host: default_host(),
+                custom: Vec::new(),
+                palette_exports: default_exports(),
+                stop: default_stop(),
+                playlists: HashMap::new(),
+                rules: Vec::new(),
+                rules_interval: default_rules_interval(),
+                outputs_command: default_outputs_command(),
             }
         }
+        /// Variant of the last loaded theme, if it was loaded with one
+        pub fn last_variant(&self) -> Option<&str> {
+            Some(self.last_variant.as_str()).filter(|x| !x.is_empty())
+        }
     }
     /// Check to see if there are themes still using the old format
-    pub fn check_themes() {
-        let entries = get_themes();
+    pub fn check_themes(paths: &RavenPaths) -> Result<(), RavenError> {
+        let entries = get_themes(paths)?;
         for entry in entries {
-            if fs::metadata(get_home() + "/.config/raven/themes/" + &entry + "/theme").is_ok() {
-                convert_theme(entry);
+            if fs::metadata(paths.theme_file(entry.as_str(), "theme")).is_ok() {
+                convert_theme(paths, entry)?;
             }
         }
+        Ok(())
     }
-    /// Create base raven directories and config file(s)
-    pub fn init() {
-        if fs::metadata(get_home() + "/.config/raven/config").is_err() {
-            fs::create_dir(get_home() + "/.config/raven").unwrap();
-            fs::create_dir(get_home() + "/.config/raven/themes").unwrap();
-        } else {
-            println!(
-                    "The config file format has changed. Please check ~/.config/raven/config.json to reconfigure raven."
-                );
+    /// Create base raven directories and config file(s). Returns true if a config file in the
+    /// old format was found
+    pub fn init(paths: &RavenPaths) -> Result<bool, RavenError> {
+        let old = fs::metadata(paths.root_file("config")).is_ok();
+        if !old {
+            fs::create_dir_all(paths.themes())?;
         }
         let mut file = OpenOptions::new()
             .create(true)
             .write(true)
-            .open(get_home() + "/.config/raven/config.json")
-            .unwrap();
-        let default = serde_json::to_string(&Config::default()).unwrap();
-        file.write_all(default.as_bytes()).unwrap();
-        println!("Correctly initialized base config. Please run again to use raven.");
+            .open(paths.config_file())?;
+        let default = serde_json::to_string(&Config::default())?;
+        file.write_all(default.as_bytes())?;
+        Ok(old)
     }
     /// Checks to see if base config/directories need to be initialized
-    pub fn check_init() -> bool {
-        fs::metadata(get_home() + "/.config/raven").is_err()
-            || fs::metadata(get_home() + "/.config/raven/config.json").is_err()
-            || fs::metadata(get_home() + "/.config/raven/themes").is_err()
+    pub fn check_init(paths: &RavenPaths) -> bool {
+        fs::metadata(&paths.root).is_err()
+            || fs::metadata(paths.config_file()).is_err()
+            || fs::metadata(paths.themes()).is_err()
     }
     /// Updates the written config with a new config
-    pub fn up_config(conf: Config) {
+    pub fn up_config(paths: &RavenPaths, conf: Config) -> Result<(), RavenError> {
         OpenOptions::new()
             .create(true)
             .write(true)
-            .open(get_home() + "/.config/raven/~config.json")
-            .expect("Couldn't open last theme file")
-            .write_all(serde_json::to_string(&conf).unwrap().as_bytes())
-            .expect("Couldn't write to last theme file");
-        fs::copy(
-            get_home() + "/.config/raven/~config.json",
-            get_home() + "/.config/raven/config.json",
-        )
-        .unwrap();
-        fs::remove_file(get_home() + "/.config/raven/~config.json").unwrap();
-    }
-    pub fn up_theme(theme: ThemeStore) {
-        let wthemepath = get_home() + "/.config/raven/themes/" + &theme.name + "/~theme.json";
-        let themepath = get_home() + "/.config/raven/themes/" + &theme.name + "/theme.json";
+            .open(paths.root_file("~config.json"))?
+            .write_all(serde_json::to_string(&conf)?.as_bytes())?;
+        fs::copy(paths.root_file("~config.json"), paths.config_file())?;
+        fs::remove_file(paths.root_file("~config.json"))?;
+        Ok(())
+    }
+    /// Updates the written theme.json of a theme
+    pub fn up_theme(paths: &RavenPaths, theme: ThemeStore) -> Result<(), RavenError> {
+        let wthemepath = paths.theme_file(theme.name.as_str(), "~theme.json");
+        let themepath = paths.theme_file(theme.name.as_str(), "theme.json");
         OpenOptions::new()
             .create(true)
             .write(true)
-            .open(&wthemepath)
-            .expect("Couldn't open theme file")
-            .write_all(serde_json::to_string(&theme).unwrap().as_bytes())
-            .expect("Couldn't write to theme file");
-        fs::copy(&wthemepath, &themepath).unwrap();
-        fs::remove_file(&wthemepath).unwrap();
+            .open(&wthemepath)?
+            .write_all(serde_json::to_string(&theme)?.as_bytes())?;
+        fs::copy(&wthemepath, &themepath)?;
+        fs::remove_file(&wthemepath)?;
+        Ok(())
     }
-
-    pub fn convert_theme<N>(theme_name: N)
+    /// Converts a theme from the old single-file format to theme.json
+    pub fn convert_theme<N>(paths: &RavenPaths, theme_name: N) -> Result<(), RavenError>
     where
         N: Into<String>,
     {
         let theme_name = theme_name.into();
         let mut theme = String::new();
-        let otp = get_home() + "/.config/raven/themes/" + &theme_name + "/theme";
-        fs::File::open(&otp)
-            .expect("Couldn't read theme")
-            .read_to_string(&mut theme)
-            .unwrap();
+        let otp = paths.theme_file(theme_name.as_str(), "theme");
+        fs::File::open(&otp)?.read_to_string(&mut theme)?;
         let options = theme
             .split('|')
             .map(|x| String::from(String::from(x).trim()))
             .filter(|x| x.len() > 0)
             .filter(|x| x != "|")
             .collect::<Vec<String>>();
-        fs::remove_file(otp).unwrap();
+        fs::remove_file(otp)?;
         let themes = ThemeStore {
             name: theme_name.clone(),
+            extends: None,
+            variants: Default::default(),
+            tags: Vec::new(),
+            templates: Vec::new(),
             enabled: Vec::new(),
+            disabled: Vec::new(),
             options: options,
             screenshot: default_screen(),
             description: default_desc(),
-            kv: Map::new()
+            kv: Map::new(),
+            palette: Palette::default(),
         };
         OpenOptions::new()
             .create(true)
             .write(true)
-            .open(get_home() + "/.config/raven/themes/" + &theme_name + "/theme.json")
-            .expect("Can't open theme.json")
-            .write_all(serde_json::to_string(&themes).unwrap().as_bytes())
-            .unwrap();
+            .open(paths.theme_file(theme_name.as_str(), "theme.json"))?
+            .write_all(serde_json::to_string(&themes)?.as_bytes())?;
+        Ok(())
     }
-    pub fn load_store<N>(theme: N) -> ThemeStore
+    /// Reads in the stored theme.json of a theme
+    pub fn load_store<N>(paths: &RavenPaths, theme: N) -> Result<ThemeStore, RavenError>
     where
         N: Into<String>,
     {
         let theme = theme.into();
         let mut st = String::new();
-        fs::File::open(get_home() + "/.config/raven/themes/" + &theme + "/theme.json")
-            .unwrap()
-            .read_to_string(&mut st)
-            .unwrap();
-        serde_json::from_str(&st).unwrap()
+        match fs::File::open(paths.theme_file(theme.as_str(), "theme.json")) {
+            Ok(mut file) => file.read_to_string(&mut st)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(RavenError::MissingTheme(theme));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut store: ThemeStore = serde_json::from_str(&st)?;
+        store.convert_enabled();
+        Ok(store)
+    }
+    /// Reads in the stored theme.json of a theme, with the options, key-value options and palette
+    /// of the themes it extends filled in where it doesn't override them. Also returns which
+    /// theme each option and key comes from
+    pub fn resolve_store<N>(
+        paths: &RavenPaths,
+        theme: N,
+    ) -> Result<(ThemeStore, HashMap<String, String>), RavenError>
+    where
+        N: Into<String>,
+    {
+        let theme = theme.into();
+        let mut chain = vec![(theme.clone(), load_store(paths, theme.as_str())?)];
+        while let Some(parent) = chain[chain.len() - 1].1.extends.clone() {
+            if chain.iter().any(|(name, _)| *name == parent) {
+                return Err(RavenError::Invalid(format!(
+                    "Theme {} extends itself through {}",
+                    parent,
+                    chain[chain.len() - 1].0
+                )));
+            }
+            let store = load_store(paths, parent.as_str())?;
+            chain.push((parent, store));
+        }
+        // Start from the furthest ancestor, and apply each child over its parent
+        let (name, mut resolved) = chain.pop().expect("chain starts with the theme itself");
+        let mut origins = resolved
+            .options
+            .iter()
+            .chain(resolved.kv.keys())
+            .map(|x| (x.clone(), name.clone()))
+            .collect::<HashMap<String, String>>();
+        while let Some((name, child)) = chain.pop() {
+            for option in child.options {
+                origins.insert(option.clone(), name.clone());
+                if !resolved.options.contains(&option) {
+                    resolved.options.push(option);
+                }
+            }
+            for (key, value) in child.kv {
+                origins.insert(key.clone(), name.clone());
+                resolved.kv.insert(key, value);
+            }
+            // Whether an option is a template goes with its file, so it comes from the theme the
+            // option does
+            let (inherited, own) = (resolved.templates.clone(), child.templates);
+            resolved.templates = origins
+                .iter()
+                .filter(|(option, origin)| {
+                    if **origin == name {
+                        own.contains(option)
+                    } else {
+                        inherited.contains(option)
+                    }
+                })
+                .map(|(option, _)| option.clone())
+                .collect();
+            resolved.palette.merge(&child.palette);
+            resolved.name = child.name;
+            resolved.extends = child.extends;
+            resolved.enabled = child.enabled;
+            resolved.disabled = child.disabled;
+            resolved.variants = child.variants;
+            resolved.tags = child.tags;
+            resolved.screenshot = child.screenshot;
+            resolved.description = child.description;
+        }
+        Ok((resolved, origins))
     }
     /// Load in data for and run loading methods for a specific theme
-    pub fn load_theme<N>(theme_name: N) -> Result<Theme, &'static str>
+    pub fn load_theme<N>(paths: &RavenPaths, theme_name: N) -> Result<Theme, RavenError>
     where
         N: Into<String>,
     {
         let theme_name = theme_name.into();
-
-        let conf = get_config();
-        let ent_res = fs::read_dir(get_home() + "/.config/raven/themes/" + &theme_name);
-        if ent_res.is_ok() {
-            println!("Found theme {}", theme_name);
-            if fs::metadata(get_home() + "/.config/raven/themes/" + &theme_name + "/theme.json")
-                .is_ok()
-            {
-                let theme_info = load_store(theme_name.as_str());
-                let opts: Vec<String> = theme_info.options;
-                let new_theme = Theme {
-                    name: theme_name,
-                    options: opts,
-                    monitor: conf.monitors,
-                    enabled: theme_info.enabled,
-                    order: conf.polybar,
-                    kv: theme_info.kv
-                };
-                Ok(new_theme)
-            } else {
-                Err("Can't find Theme data")
-            }
-        } else {
-            println!("Theme does not exist.");
-            Err("Theme does not exist")
+        let conf = get_config(paths)?;
+        if fs::metadata(paths.theme(theme_name.as_str())).is_err() {
+            return Err(RavenError::MissingTheme(theme_name));
         }
+        let (theme_info, origins) = resolve_store(paths, theme_name.as_str())?;
+        Ok(Theme {
+            paths: paths.clone(),
+            name: theme_name,
+            options: theme_info.options,
+            monitor: conf.monitors,
+            disabled: theme_info.disabled,
+            order: conf.polybar,
+            kv: theme_info.kv,
+            palette: theme_info.palette,
+            origins,
+            variants: theme_info.variants,
+            templates: theme_info.templates,
+            variant: None,
+            selection: Selection::default(),
+            scratch: None,
+        })
     }
     /// Retrieve config settings from file
-    pub fn get_config() -> Config {
+    pub fn get_config(paths: &RavenPaths) -> Result<Config, RavenError> {
         let mut conf = String::new();
-        fs::File::open(get_home() + "/.config/raven/config.json")
-            .expect("Couldn't read config")
-            .read_to_string(&mut conf)
-            .unwrap();
-        serde_json::from_str(&conf).expect("Couldn't read config file")
+        fs::File::open(paths.config_file())?.read_to_string(&mut conf)?;
+        Ok(serde_json::from_str(&conf)?)
     }
 }
 /// Ravend control
 pub mod daemon {
-    use std::process::Command;
-    /// Starts ravend
-    pub fn start_daemon() {
-        Command::new("sh")
-            .arg("-c")
-            .arg("ravend")
-            .spawn()
-            .expect("Couldn't start daemon.");
-        println!("Started cycle daemon.");
-    }
-    /// Stops ravend
-    pub fn stop_daemon() {
-        Command::new("pkill")
-            .arg("-SIGKILL")
-            .arg("ravend")
-            .output()
-            .expect("Couldn't stop daemon.");
-        println!("Stopped cycle daemon.");
+    use crate::error::RavenError;
+    use crate::paths::RavenPaths;
+    use crate::runner::{Cmd, CommandRunner};
+    use libc;
+    use std::{
+        fs,
+        fs::{File, OpenOptions},
+        io,
+        io::{Read, Write},
+        os::unix::io::AsRawFd,
+        process, thread,
+        time::{Duration, Instant},
+    };
+    /// How long `stop_daemon` waits for ravend to exit
+    const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+    /// Tries to take a flock on a file without waiting. Returns whether it was taken
+    fn try_lock(file: &File, operation: libc::c_int) -> Result<bool, RavenError> {
+        if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+            return Ok(true);
+        }
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() == Some(libc::EWOULDBLOCK) {
+            Ok(false)
+        } else {
+            Err(e.into())
+        }
+    }
+    /// Locks ravend's pidfile and writes the current PID into it. The lock is held until the
+    /// returned file is closed, which the kernel does even if ravend is killed, so a pidfile left
+    /// behind is never taken for a running daemon
+    pub fn lock_pidfile(paths: &RavenPaths) -> Result<File, RavenError> {
+        fs::create_dir_all(&paths.runtime)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            // Truncated once locked, so a running ravend's PID isn't wiped out
+            .truncate(false)
+            .open(paths.pidfile())?;
+        if !try_lock(&file, libc::LOCK_EX)? {
+            return Err(RavenError::Invalid(
+                "Cycle daemon already running.".to_string(),
+            ));
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", process::id())?;
+        Ok(file)
+    }
+    /// PID of the running ravend, or None if it isn't running
+    pub fn daemon_pid(paths: &RavenPaths) -> Result<Option<u32>, RavenError> {
+        let mut file = match File::open(paths.pidfile()) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if try_lock(&file, libc::LOCK_SH)? {
+            // Nothing holds the pidfile, so it was left behind by a ravend that is gone
+            return Ok(None);
+        }
+        let mut pid = String::new();
+        file.read_to_string(&mut pid)?;
+        match pid.trim().parse() {
+            Ok(pid) => Ok(Some(pid)),
+            Err(_) => Err(RavenError::Invalid(format!(
+                "ravend is running, but its pidfile {} has no PID in it.",
+                paths.pidfile()
+            ))),
+        }
+    }
+    /// Starts ravend, using the same raven root, cycling through a playlist or all themes
+    pub fn start_daemon(
+        paths: &RavenPaths,
+        runner: &dyn CommandRunner,
+        playlist: Option<String>,
+    ) -> Result<(), RavenError> {
+        let mut cmd = Cmd::new("ravend").env("RAVEN_HOME", paths.root.as_str());
+        if let Some(playlist) = playlist {
+            cmd = cmd.arg(playlist);
+        }
+        runner.spawn(&cmd)?;
+        Ok(())
+    }
+    /// Asks ravend to shut down with SIGTERM, and waits until it has
+    pub fn stop_daemon(paths: &RavenPaths, runner: &dyn CommandRunner) -> Result<(), RavenError> {
+        let pid = match daemon_pid(paths)? {
+            Some(pid) => pid,
+            None => {
+                return Err(RavenError::Invalid("Cycle daemon not running.".to_string()));
+            }
+        };
+        let out = runner.output(&Cmd::new("kill").arg("-TERM").arg(pid.to_string()))?;
+        if !out.success() {
+            return Err(RavenError::Invalid(format!(
+                "Couldn't stop ravend: {}",
+                out.stderr.trim()
+            )));
+        }
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while daemon_pid(paths)?.is_some() {
+            if Instant::now() >= deadline {
+                return Err(RavenError::Invalid(format!(
+                    "ravend (PID {}) didn't stop within {} seconds.",
+                    pid,
+                    STOP_TIMEOUT.as_secs()
+                )));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Ok(())
     }
     /// Checks if the ravend daemon is running
-    pub fn check_daemon() -> bool {
-        let out = Command::new("ps")
-            .arg("aux")
-            .output()
-            .expect("Couldn't find daemon");
-        let form_out = String::from_utf8_lossy(&out.stdout);
-        let line_num = form_out.lines().filter(|x| x.contains("ravend")).count();
-        line_num > 0
+    pub fn check_daemon(paths: &RavenPaths) -> Result<bool, RavenError> {
+        Ok(daemon_pid(paths)?.is_some())
     }
-
 }
 
 /// Converts DirEntry into a fully processed file/directory name
 pub fn proc_path(path: DirEntry) -> String {
-    path.file_name().into_string().unwrap()
+    path.file_name().to_string_lossy().into_owned()
 }
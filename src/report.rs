@@ -0,0 +1,100 @@
+use std::time::Duration;
+/// What happened to one option while loading a theme
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    /// The option's files were written and its commands ran successfully
+    Applied,
+    /// The option wasn't loaded, for the given reason
+    Skipped(String),
+    /// One of the option's commands exited unsuccessfully
+    Failed {
+        command: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+}
+/// The result of loading one option
+#[derive(Clone, Debug)]
+pub struct OptionReport {
+    pub option: String,
+    pub status: Status,
+    /// Time spent preparing the option and running its commands
+    pub duration: Duration,
+}
+/// The result of loading a theme, option by option
+#[derive(Clone, Debug, Default)]
+pub struct LoadReport {
+    pub theme: String,
+    pub options: Vec<OptionReport>,
+}
+impl LoadReport {
+    pub fn new<N>(theme: N) -> LoadReport
+    where
+        N: Into<String>,
+    {
+        LoadReport {
+            theme: theme.into(),
+            options: Vec::new(),
+        }
+    }
+    /// Adds an option that was staged for loading
+    pub fn applied<N>(&mut self, option: N, duration: Duration)
+    where
+        N: Into<String>,
+    {
+        self.options.push(OptionReport {
+            option: option.into(),
+            status: Status::Applied,
+            duration,
+        });
+    }
+    /// Adds an option that wasn't loaded
+    pub fn skipped<N, S>(&mut self, option: N, reason: S)
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        self.options.push(OptionReport {
+            option: option.into(),
+            status: Status::Skipped(reason.into()),
+            duration: Duration::default(),
+        });
+    }
+    fn find(&mut self, option: &str) -> Option<&mut OptionReport> {
+        self.options.iter_mut().find(|x| x.option == option)
+    }
+    /// Adds time spent on an option
+    pub fn add_time(&mut self, option: &str, duration: Duration) {
+        if let Some(report) = self.find(option) {
+            report.duration += duration;
+        }
+    }
+    /// Marks an option as failed. Only its first failure is kept
+    pub fn fail(&mut self, option: &str, status: Status) {
+        if let Some(report) = self.find(option) {
+            if report.status == Status::Applied {
+                report.status = status;
+            }
+        }
+    }
+    /// Options that weren't loaded
+    pub fn skipped_options(&self) -> Vec<String> {
+        self.options
+            .iter()
+            .filter(|x| matches!(x.status, Status::Skipped(_)))
+            .map(|x| x.option.clone())
+            .collect()
+    }
+    /// Options with a command that failed
+    pub fn failed_options(&self) -> Vec<String> {
+        self.options
+            .iter()
+            .filter(|x| matches!(x.status, Status::Failed { .. }))
+            .map(|x| x.option.clone())
+            .collect()
+    }
+    /// Total time spent loading
+    pub fn duration(&self) -> Duration {
+        self.options.iter().map(|x| x.duration).sum()
+    }
+}
@@ -0,0 +1,277 @@
+use error::RavenError;
+use libc;
+use paths::RavenPaths;
+use runner::{Cmd, CommandRunner};
+use std::{env, ffi::CStr, fmt, fs, io, path::Path};
+use time;
+/// Days of the week as rules name them, starting on Sunday like `Tm::tm_wday`
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+/// Where sysfs is mounted, unless `$RAVEN_SYSFS` says otherwise
+pub fn sysfs_root() -> String {
+    env::var("RAVEN_SYSFS").unwrap_or_else(|_| "/sys".to_string())
+}
+/// Where the machine gets its power from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Power {
+    Ac,
+    Battery,
+}
+impl fmt::Display for Power {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Power::Ac => write!(f, "AC"),
+            Power::Battery => write!(f, "battery"),
+        }
+    }
+}
+/// A theme to switch to when all of the conditions given are met. A rule without conditions
+/// always matches
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub theme: String,
+    /// Start of the time of day the rule applies, as HH:MM
+    #[serde(default)]
+    pub from: Option<String>,
+    /// End of the time of day the rule applies, as HH:MM. Before `from` to go past midnight
+    #[serde(default)]
+    pub until: Option<String>,
+    /// Days of the week the rule applies on, like mon or sat
+    #[serde(default)]
+    pub days: Vec<String>,
+    #[serde(default)]
+    pub power: Option<Power>,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Number of connected outputs, as counted by `outputs_command` in the config
+    #[serde(default)]
+    pub outputs: Option<u32>,
+    /// A file that has to exist. A leading ~ is expanded to the home directory
+    #[serde(default)]
+    pub file: Option<String>,
+    /// An environment variable that has to be set and not empty
+    #[serde(default)]
+    pub env: Option<String>,
+}
+/// The state of the machine that rules are checked against
+#[derive(Debug, Clone)]
+pub struct Facts {
+    pub now: time::Tm,
+    pub power: Power,
+    pub hostname: String,
+    /// None if no rule needs it, or the probe command failed
+    pub outputs: Option<u32>,
+}
+impl Facts {
+    /// Reads the current state of the machine. The outputs are only probed when a rule asks
+    /// about them
+    pub fn gather(
+        rules: &[Rule],
+        outputs_command: &str,
+        sysfs: &str,
+        runner: &dyn CommandRunner,
+    ) -> Result<Facts, RavenError> {
+        let outputs = if rules.iter().any(|x| x.outputs.is_some()) {
+            count_outputs(outputs_command, runner)
+        } else {
+            None
+        };
+        Ok(Facts {
+            now: time::now(),
+            power: power_source(sysfs)?,
+            hostname: hostname()?,
+            outputs,
+        })
+    }
+    /// Today, as rules name the days of the week
+    pub fn day(&self) -> &'static str {
+        DAYS[self.now.tm_wday as usize % 7]
+    }
+}
+/// Whether the machine runs on AC or battery, from the power supplies in sysfs. Machines without
+/// a battery are on AC
+pub fn power_source(sysfs: &str) -> Result<Power, RavenError> {
+    let dir = Path::new(sysfs).join("class/power_supply");
+    let supplies = match fs::read_dir(&dir) {
+        Ok(supplies) => supplies,
+        Err(_) => return Ok(Power::Ac),
+    };
+    let mut battery = false;
+    for supply in supplies {
+        let supply = supply?.path();
+        let read = |file| {
+            fs::read_to_string(supply.join(file))
+                .map(|x| x.trim().to_string())
+                .unwrap_or_default()
+        };
+        match read("type").as_str() {
+            "Mains" | "USB" if read("online") == "1" => return Ok(Power::Ac),
+            "Battery" => battery = true,
+            _ => {}
+        }
+    }
+    Ok(if battery { Power::Battery } else { Power::Ac })
+}
+/// The machine's hostname
+fn hostname() -> Result<String, RavenError> {
+    let mut buf = [0 as libc::c_char; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned())
+}
+/// Runs the outputs probe command, which should print the number of connected outputs
+fn count_outputs(command: &str, runner: &dyn CommandRunner) -> Option<u32> {
+    let out = runner.output(&Cmd::new("sh").arg("-c").arg(command)).ok()?;
+    out.stdout.trim().parse().ok()
+}
+/// Minutes since midnight of an HH:MM time
+fn parse_time(rule: &Rule, time: &str) -> Result<i32, RavenError> {
+    let mut parts = time.splitn(2, ':').map(|x| x.trim().parse::<i32>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(hour)), Some(Ok(minute)))
+            if (0..24).contains(&hour) && (0..60).contains(&minute) =>
+        {
+            Ok(hour * 60 + minute)
+        }
+        _ => Err(RavenError::Invalid(format!(
+            "Rule {} has an invalid time {}. Use HH:MM.",
+            rule.name, time
+        ))),
+    }
+}
+impl Rule {
+    /// Whether all of the rule's conditions are met. Errors if the rule is malformed
+    // is_none_or needs Rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn matches(&self, paths: &RavenPaths, facts: &Facts) -> Result<bool, RavenError> {
+        let minute = facts.now.tm_hour * 60 + facts.now.tm_min;
+        let from = match self.from {
+            Some(ref from) => parse_time(self, from)?,
+            None => 0,
+        };
+        let until = match self.until {
+            Some(ref until) => parse_time(self, until)?,
+            None => 24 * 60,
+        };
+        let in_time = if from <= until {
+            minute >= from && minute < until
+        } else {
+            minute >= from || minute < until
+        };
+        for day in &self.days {
+            if !DAYS.contains(&day.to_lowercase().as_str()) {
+                return Err(RavenError::Invalid(format!(
+                    "Rule {} has an invalid day {}. Use one of {}.",
+                    self.name,
+                    day,
+                    DAYS.join(", ")
+                )));
+            }
+        }
+        let on_day =
+            self.days.is_empty() || self.days.iter().any(|x| x.to_lowercase() == facts.day());
+        Ok(in_time
+            && on_day
+            && self.power.map_or(true, |x| x == facts.power)
+            && self
+                .hostname
+                .as_ref()
+                .map_or(true, |x| *x == facts.hostname)
+            && self.outputs.map_or(true, |x| facts.outputs == Some(x))
+            && self
+                .file
+                .as_ref()
+                .map_or(true, |x| Path::new(&paths.expand(x.as_str())).exists())
+            && self.env.as_ref().map_or(true, |x| {
+                env::var(x).map(|x| !x.is_empty()).unwrap_or(false)
+            }))
+    }
+}
+/// The first rule that matches
+pub fn matching_rule<'a>(
+    paths: &RavenPaths,
+    rules: &'a [Rule],
+    facts: &Facts,
+) -> Result<Option<&'a Rule>, RavenError> {
+    for rule in rules {
+        if rule.matches(paths, facts)? {
+            return Ok(Some(rule));
+        }
+    }
+    Ok(None)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    fn rule(json: &str) -> Rule {
+        serde_json::from_str(json).unwrap()
+    }
+    /// Facts for a given time on a given day, counted from Sunday
+    fn facts(hour: i32, minute: i32, wday: i32) -> Facts {
+        let mut now = time::empty_tm();
+        now.tm_hour = hour;
+        now.tm_min = minute;
+        now.tm_wday = wday;
+        Facts {
+            now,
+            power: Power::Ac,
+            hostname: "host".to_string(),
+            outputs: None,
+        }
+    }
+    #[test]
+    fn time_window_wraps_past_midnight() {
+        let paths = RavenPaths::temp("rules-time");
+        let night =
+            rule(r#"{"name": "night", "theme": "dark", "from": "22:00", "until": "06:00"}"#);
+        assert!(night.matches(&paths, &facts(22, 0, 1)).unwrap());
+        assert!(night.matches(&paths, &facts(23, 30, 1)).unwrap());
+        assert!(night.matches(&paths, &facts(5, 59, 2)).unwrap());
+        assert!(!night.matches(&paths, &facts(6, 0, 2)).unwrap());
+        assert!(!night.matches(&paths, &facts(12, 0, 2)).unwrap());
+    }
+    #[test]
+    fn days_filter_by_weekday() {
+        let paths = RavenPaths::temp("rules-days");
+        let weekend = rule(r#"{"name": "weekend", "theme": "fun", "days": ["sat", "Sun"]}"#);
+        assert!(weekend.matches(&paths, &facts(12, 0, 6)).unwrap());
+        assert!(weekend.matches(&paths, &facts(12, 0, 0)).unwrap());
+        assert!(!weekend.matches(&paths, &facts(12, 0, 1)).unwrap());
+        let typo = rule(r#"{"name": "typo", "theme": "fun", "days": ["saturday"]}"#);
+        assert!(typo.matches(&paths, &facts(12, 0, 6)).is_err());
+    }
+    #[test]
+    fn invalid_times_are_errors() {
+        let paths = RavenPaths::temp("rules-invalid");
+        for time in &["24:00", "12:60", "noon", "12", "-1:30", ""] {
+            let bad = rule(&format!(
+                r#"{{"name": "bad", "theme": "dark", "from": "{}"}}"#,
+                time
+            ));
+            assert!(bad.matches(&paths, &facts(12, 0, 1)).is_err(), "{}", time);
+        }
+    }
+    #[test]
+    fn power_source_reads_sysfs() {
+        let paths = RavenPaths::temp("rules-power");
+        let sysfs = paths.home.clone() + "/sys";
+        let supply = |name: &str, kind: &str, online: &str| {
+            let dir = sysfs.clone() + "/class/power_supply/" + name;
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.clone() + "/type", kind.to_string() + "\n").unwrap();
+            fs::write(dir + "/online", online.to_string() + "\n").unwrap();
+        };
+        // No power supplies at all, as on a desktop
+        assert_eq!(power_source(&sysfs).unwrap(), Power::Ac);
+        supply("BAT0", "Battery", "0");
+        supply("AC", "Mains", "0");
+        assert_eq!(power_source(&sysfs).unwrap(), Power::Battery);
+        supply("AC", "Mains", "1");
+        assert_eq!(power_source(&sysfs).unwrap(), Power::Ac);
+    }
+}
@@ -0,0 +1,190 @@
+use error::RavenError;
+use std::{
+    cell::RefCell, collections::HashMap, fmt, io, os::unix::process::CommandExt, process::Command,
+};
+/// An external command raven runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cmd {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Environment variables set for the command, on top of raven's own
+    pub env: Vec<(String, String)>,
+    /// Started without waiting for it to finish, for programs like bars that keep running
+    pub background: bool,
+    /// Whether exiting unsuccessfully means the command failed
+    pub check: bool,
+}
+impl Cmd {
+    pub fn new<N>(program: N) -> Cmd
+    where
+        N: Into<String>,
+    {
+        Cmd {
+            program: program.into(),
+            args: Vec::new(),
+            env: Vec::new(),
+            background: false,
+            check: true,
+        }
+    }
+    pub fn arg<N>(mut self, arg: N) -> Cmd
+    where
+        N: Into<String>,
+    {
+        self.args.push(arg.into());
+        self
+    }
+    pub fn env<N, S>(mut self, key: N, value: S) -> Cmd
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+    /// Marks the command to be started in the background
+    pub fn background(mut self) -> Cmd {
+        self.background = true;
+        self
+    }
+    /// Marks the command's exit code as meaningless, as for signalling a program that may not be
+    /// running
+    pub fn unchecked(mut self) -> Cmd {
+        self.check = false;
+        self
+    }
+}
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in &self.env {
+            write!(f, "{}={} ", key, value)?;
+        }
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            if arg.contains(' ') || arg.is_empty() {
+                write!(f, " '{}'", arg)?;
+            } else {
+                write!(f, " {}", arg)?;
+            }
+        }
+        if self.background {
+            write!(f, " &")?;
+        }
+        Ok(())
+    }
+}
+/// What a command that was waited for produced
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Output {
+    /// Exit code, or None if the command was killed by a signal
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+impl Output {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+/// Runs external commands. Every process raven starts goes through one of these, so they can be
+/// logged, intercepted or mocked
+pub trait CommandRunner {
+    /// Runs a command and waits for it to finish
+    fn output(&self, cmd: &Cmd) -> Result<Output, RavenError>;
+    /// Starts a command without waiting for it, in a new process group. Returns its PID, which is
+    /// also the process group
+    fn spawn(&self, cmd: &Cmd) -> Result<u32, RavenError>;
+    /// Runs a command in the background or waits for it, as it asks. Returns the PID of
+    /// background commands. Only failing to start it is an error
+    fn run(&self, cmd: &Cmd) -> Result<Option<u32>, RavenError> {
+        if cmd.background {
+            Ok(Some(self.spawn(cmd)?))
+        } else {
+            self.output(cmd)?;
+            Ok(None)
+        }
+    }
+}
+/// Runs commands as real processes
+pub struct SystemRunner;
+impl SystemRunner {
+    fn command(cmd: &Cmd) -> Command {
+        let mut command = Command::new(&cmd.program);
+        command.args(&cmd.args);
+        for (key, value) in &cmd.env {
+            command.env(key, value);
+        }
+        command
+    }
+}
+impl CommandRunner for SystemRunner {
+    fn output(&self, cmd: &Cmd) -> Result<Output, RavenError> {
+        let out = SystemRunner::command(cmd).output()?;
+        Ok(Output {
+            code: out.status.code(),
+            stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        })
+    }
+    fn spawn(&self, cmd: &Cmd) -> Result<u32, RavenError> {
+        Ok(SystemRunner::command(cmd).process_group(0).spawn()?.id())
+    }
+}
+/// Records commands instead of running them. Programs can be given canned output, or marked as
+/// missing to make running them fail like an uninstalled program would
+#[derive(Default)]
+pub struct RecordingRunner {
+    commands: RefCell<Vec<Cmd>>,
+    outputs: HashMap<String, Output>,
+    missing: Vec<String>,
+}
+impl RecordingRunner {
+    pub fn new() -> RecordingRunner {
+        RecordingRunner::default()
+    }
+    /// Makes commands running `program` return `output`. Others exit successfully without output
+    pub fn with_output<N>(mut self, program: N, output: Output) -> RecordingRunner
+    where
+        N: Into<String>,
+    {
+        self.outputs.insert(program.into(), output);
+        self
+    }
+    /// Makes commands running `program` fail to start
+    pub fn with_missing<N>(mut self, program: N) -> RecordingRunner
+    where
+        N: Into<String>,
+    {
+        self.missing.push(program.into());
+        self
+    }
+    /// All commands run so far, in order
+    pub fn commands(&self) -> Vec<Cmd> {
+        self.commands.borrow().clone()
+    }
+    fn record(&self, cmd: &Cmd) -> Result<(), RavenError> {
+        self.commands.borrow_mut().push(cmd.clone());
+        if self.missing.contains(&cmd.program) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not installed", cmd.program),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+impl CommandRunner for RecordingRunner {
+    fn output(&self, cmd: &Cmd) -> Result<Output, RavenError> {
+        self.record(cmd)?;
+        Ok(self.outputs.get(&cmd.program).cloned().unwrap_or(Output {
+            code: Some(0),
+            ..Output::default()
+        }))
+    }
+    fn spawn(&self, cmd: &Cmd) -> Result<u32, RavenError> {
+        self.record(cmd)?;
+        // Fake PIDs, counting up from 1
+        Ok(self.commands.borrow().len() as u32)
+    }
+}
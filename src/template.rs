@@ -0,0 +1,65 @@
+use error::RavenError;
+use serde_json::value::{Map, Value};
+/// Renders a template, replacing each `{{ name }}` with the matching variable. Dotted names like
+/// `color.bg` are looked up as a key of that name first, then as a path through nested objects.
+/// `{{{{` is an escape, and renders as a literal `{{`
+pub fn render<N>(template: N, vars: &Map<String, Value>) -> Result<String, RavenError>
+where
+    N: Into<String>,
+{
+    let template = template.into();
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        if rest[start..].starts_with("{{{{") {
+            out.push_str("{{");
+            rest = &rest[start + 4..];
+            continue;
+        }
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| RavenError::Invalid("Unclosed {{ in template".to_string()))?;
+        let name = after[..end].trim();
+        let value = lookup(vars, name)
+            .ok_or_else(|| RavenError::Invalid(format!("Unknown template variable {}", name)))?;
+        out.push_str(&value);
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+/// Finds the value of a variable, as a string
+pub fn lookup(vars: &Map<String, Value>, name: &str) -> Option<String> {
+    if let Some(value) = vars.get(name) {
+        return value_string(value);
+    }
+    let mut parts = name.split('.');
+    let mut cur = vars.get(parts.next()?)?;
+    for part in parts {
+        cur = cur.as_object()?.get(part)?;
+    }
+    value_string(cur)
+}
+/// Converts a plain JSON value into the string that is put into templates
+fn value_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+/// Merges two sets of variables, with the values in `over` taking precedence. Nested objects are
+/// merged key by key
+pub fn merge_vars(base: &mut Map<String, Value>, over: &Map<String, Value>) {
+    for (k, v) in over {
+        match (base.get_mut(k), v) {
+            (Some(Value::Object(ref mut b)), Value::Object(o)) => merge_vars(b, o),
+            _ => {
+                base.insert(k.clone(), v.clone());
+            }
+        }
+    }
+}
@@ -1,607 +1,1055 @@
 use crate::config::*;
+use crate::error::RavenError;
+use crate::paths::RavenPaths;
+use extract;
+use loaders::LoaderRegistry;
+use palette::{Palette, EXPORT_FORMATS};
 use proc_path;
+use procs::{load_spawned, save_spawned, Spawned};
+use report::LoadReport;
+use runner::CommandRunner;
 use serde_json::value::{Map, Value};
 use std::{
-    env, fs, fs::DirEntry, fs::OpenOptions, io, io::Read, io::Write, os::unix::fs::OpenOptionsExt,
-    process::Command,
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    fs::DirEntry,
+    fs::OpenOptions,
+    io,
+    io::Read,
+    io::Write,
+    path::Path,
+    process,
+    str::FromStr,
+    time::Instant,
 };
+use template;
+use transaction::Transaction;
 /// Structure for holding theme info, stored in theme.json
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ThemeStore {
     pub name: String,
+    /// Theme whose options, key-value options and palette this theme builds on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub options: Vec<String>,
+    /// Options that are loaded, as older versions stored it. Empty means all of them are. Turned
+    /// into `disabled` when the theme is read, so it stays empty
     pub enabled: Vec<String>,
+    /// Options that aren't loaded. Not inherited, so options added later, or inherited from the
+    /// theme this one extends, load by default
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled: Vec<String>,
     #[serde(default = "default_screen")]
     pub screenshot: String,
     #[serde(default = "default_desc")]
     pub description: String,
     #[serde(default)]
     pub kv: Map<String, Value>,
+    #[serde(default)]
+    pub palette: Palette,
+    /// Named variations of the theme, such as light and dark. Not inherited
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variants: BTreeMap<String, Variant>,
+    /// Labels for picking themes into ravend's playlists. Not inherited
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Options whose files are rendered as templates. Other files are used as they are
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<String>,
+}
+/// A named variation of a theme, which overrides some of its option files and key-value options
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Variant {
+    /// Options with their own file in variants/[name] in the theme's directory
+    #[serde(default)]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub kv: Map<String, Value>,
+}
+impl ThemeStore {
+    /// All options of the theme, including key-value ones
+    pub fn option_names(&self) -> Vec<String> {
+        self.options
+            .iter()
+            .filter(|x| *x != "|")
+            .chain(self.kv.keys())
+            .cloned()
+            .collect()
+    }
+    /// Whether an option is loaded with the theme
+    pub fn is_enabled(&self, option: &str) -> bool {
+        !self.disabled.iter().any(|x| x == option)
+    }
+    /// Turns an `enabled` list written by an older version into the `disabled` list that
+    /// replaced it
+    pub fn convert_enabled(&mut self) {
+        if self.enabled.is_empty() {
+            return;
+        }
+        for option in self.option_names() {
+            if !self.enabled.contains(&option) && !self.disabled.contains(&option) {
+                self.disabled.push(option);
+            }
+        }
+        self.enabled.clear();
+    }
+    /// The variant after the given one, going from the theme without a variant through each of
+    /// them in order and back
+    pub fn next_variant(&self, current: Option<&str>) -> Option<String> {
+        match current {
+            None => self.variants.keys().next().cloned(),
+            Some(current) => self
+                .variants
+                .keys()
+                .skip_while(|x| *x != current)
+                .nth(1)
+                .cloned(),
+        }
+    }
+}
+/// Options taken from an existing theme, written as `theme:option,option`
+#[derive(Clone, Debug)]
+pub struct Part {
+    pub theme: String,
+    pub options: Vec<String>,
+}
+impl FromStr for Part {
+    type Err = RavenError;
+    fn from_str(s: &str) -> Result<Part, RavenError> {
+        let mut split = s.splitn(2, ':');
+        let theme = split.next().unwrap_or("").trim().to_string();
+        let options = split
+            .next()
+            .unwrap_or("")
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<String>>();
+        if theme.is_empty() || options.is_empty() {
+            return Err(RavenError::Invalid(format!(
+                "Expected theme:option,option but got {}",
+                s
+            )));
+        }
+        Ok(Part { theme, options })
+    }
+}
+/// Which of a theme's options a load applies, for loading only part of a theme
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    /// Options to load. Empty means all of them
+    pub only: Vec<String>,
+    /// Options not to load
+    pub skip: Vec<String>,
+}
+impl Selection {
+    pub fn new(only: Vec<String>, skip: Vec<String>) -> Selection {
+        Selection { only, skip }
+    }
+    /// Whether a load with this selection applies an option
+    pub fn includes(&self, option: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|x| x == option))
+            && !self.skip.iter().any(|x| x == option)
+    }
 }
 /// Structure that holds all methods and data for individual themes.
 pub struct Theme {
+    pub paths: RavenPaths,
     pub name: String,
     pub options: Vec<String>,
     pub monitor: i32,
-    pub enabled: Vec<String>,
+    /// Options that aren't loaded
+    pub disabled: Vec<String>,
     pub order: Vec<String>,
     pub kv: Map<String, Value>,
+    pub palette: Palette,
+    /// The theme each option and key comes from, for options inherited through `extends`
+    pub origins: HashMap<String, String>,
+    pub variants: BTreeMap<String, Variant>,
+    /// Options whose files are rendered as templates
+    pub templates: Vec<String>,
+    /// The variant being loaded, if any
+    pub variant: Option<String>,
+    pub selection: Selection,
+    /// Directory templates are rendered into instead of the cache directory, for plans, which
+    /// mustn't touch files a running program may be reading. Generated palettes aren't cached
+    /// while it is set
+    pub scratch: Option<String>,
 }
 
+/// Options that used to be single-value files, and are now kept in key-value storage
+const SINGLE_OPTIONS: [&str; 4] = ["st_tmtheme", "st_scs", "st_subltheme", "vscode"];
+
 /// Methods for a loaded theme
 impl Theme {
-    /// Loads options held within theme.json key-value storage
-    pub fn load_kv(&self) {
-        for (k, v) in &self.kv {
-            self.load_k(k.as_str(), v.as_str().unwrap());
-        }
+    /// Restricts the options this theme loads
+    pub fn with_selection(mut self, selection: Selection) -> Theme {
+        self.selection = selection;
+        self
     }
-    /// Loads a single key option
-    pub fn load_k<N>(&self, k: N, v: N)
+    /// Switches to one of the theme's variants, whose options and keys override the theme's
+    pub fn with_variant<N>(mut self, variant: N) -> Result<Theme, RavenError>
     where
         N: Into<String>,
     {
-        let (k, v) = (k.into(), v.into());
-        match k.as_str() {
-            "st_tmtheme" => self.load_sublt("st_tmtheme", v.as_str()),
-            "st_scs" => self.load_sublt("st_scs", v.as_str()),
-            "st_subltheme" => self.load_sublt("st_subltheme", v.as_str()),
-            "vscode" => self.load_vscode(v.as_str()),
-            _ => println!("Unrecognized key {}", k),
+        let variant = variant.into();
+        let overrides = match self.variants.get(&variant) {
+            Some(overrides) => overrides.clone(),
+            None => {
+                return Err(RavenError::Invalid(format!(
+                    "Theme {} has no variant {}",
+                    self.name, variant
+                )));
+            }
+        };
+        for option in overrides.options {
+            if !self.options.contains(&option) {
+                self.options.push(option);
+            }
         }
-        println!("Loaded key option {}", k);
+        template::merge_vars(&mut self.kv, &overrides.kv);
+        self.variant = Some(variant);
+        Ok(self)
     }
-    /// Converts old single-string file options into key-value storage
-    pub fn convert_single<N>(&self, name: N)
+    /// Whether an option is loaded with the theme
+    pub fn is_enabled(&self, option: &str) -> bool {
+        !self.disabled.iter().any(|x| x == option)
+    }
+    /// Returns the path to one of the theme's option files, if it exists
+    pub fn option_path<N>(&self, option: N) -> Result<String, RavenError>
     where
         N: Into<String>,
     {
-        let key = name.into();
-        let mut value = String::new();
-        fs::File::open(get_home() + "/.config/raven/themes/" + &self.name + "/" + &key)
-            .expect("Couldn't open file")
-            .read_to_string(&mut value)
-            .unwrap();
-        let mut store = load_store(self.name.clone());
-        store.kv.insert(key.clone(),serde_json::Value::String(value.clone().trim().to_string()));
-        store.options = store.options.iter().filter(|x| x.as_str() != key.as_str()).map(|x|x.to_owned()).collect();
-        up_theme(store);
-        println!("Converted option {} to new key-value system", key);
-        self.load_k(key, value);
-    }
-    /// Iterates through options and loads them with submethods
-    pub fn load_all(&self) {
-        let opt = &self.options;
-        let mut i = 1;
-        let len = opt.len();
-        while i <= len {
-            let ref option = opt[len - i];
-            match option.to_lowercase().as_ref() {
-                "poly" => self.load_poly(self.monitor),
-                "wm" => self.load_i3(true),
-                "i3" => self.load_i3(false),
-                "xres" => self.load_xres(false),
-                "xres_m" => self.load_xres(true),
-                "pywal" => self.load_pywal(),
-                "wall" => self.load_wall(),
-                "ncmpcpp" => self.load_ncm(),
-                "termite" => self.load_termite(),
-                "script" => self.load_script(),
-                "bspwm" => self.load_bspwm(),
-                "rofi" => self.load_rofi(),
-                "ranger" => self.load_ranger(),
-                "lemonbar" => self.load_lemon(),
-                "openbox" => self.load_openbox(),
-                "dunst" => self.load_dunst(),
-                "st_tmtheme" => self.convert_single("st_tmtheme"),
-                "st_scs" => self.convert_single("st_scs"),
-                "st_subltheme" => self.convert_single("st_subltheme"),
-                "vscode" => self.convert_single("vscode"),
-                "|" => {}
-                _ => println!("Unknown option"),
-            };
-            if !option.contains("|") {
-                println!("Loaded option {}", option);
-            }
-            i += 1;
+        let option = option.into();
+        let owner = self.origins.get(&option).unwrap_or(&self.name);
+        let path = match self.variant {
+            Some(ref variant) if self.variants[variant].options.contains(&option) => self
+                .paths
+                .variant_file(self.name.as_str(), variant.as_str(), option.as_str()),
+            _ => self.paths.theme_file(owner.as_str(), option.as_str()),
+        };
+        if fs::metadata(&path).is_ok() {
+            Ok(path)
+        } else {
+            Err(RavenError::MissingOption(self.name.clone(), option))
         }
-        self.load_kv();
-        println!("Loaded all options for theme {}", self.name);
     }
-    /// Edits the value of a key in hjson files
-    fn edit_hjson<N, S, T>(&self, file: N, pat: S, value: T)
+    /// Returns the template variables for this theme: the defaults from variables.json,
+    /// overridden by the theme's key-value storage. The palette is available as `palette`
+    pub fn variables(&self) -> Result<Map<String, Value>, RavenError> {
+        let mut vars = Map::new();
+        if fs::metadata(self.paths.variables()).is_ok() {
+            let mut defaults = String::new();
+            fs::File::open(self.paths.variables())?.read_to_string(&mut defaults)?;
+            vars = serde_json::from_str(&defaults)?;
+        }
+        let mut palette = Map::new();
+        palette.insert("palette".to_string(), Value::Object(self.palette.to_vars()));
+        template::merge_vars(&mut vars, &palette);
+        template::merge_vars(&mut vars, &self.kv);
+        Ok(vars)
+    }
+    /// Whether an option's file is rendered as a template
+    pub fn is_template(&self, option: &str) -> bool {
+        self.templates.iter().any(|x| x == option)
+    }
+    /// Renders text with the theme's variables
+    pub fn render<N>(&self, text: N) -> Result<String, RavenError>
     where
         N: Into<String>,
-        S: Into<String>,
-        T: Into<String>,
     {
-        let file = &file.into();
-        let pat = &pat.into();
-        let value = &value.into();
-        let mut finals = String::new();
-        if fs::metadata(file).is_ok() {
-            let mut pre = String::new();
-            fs::File::open(file)
-                .expect("Couldn't open hjson file")
-                .read_to_string(&mut pre)
-                .unwrap();
-            let mut patfound = false;
-            for line in pre.lines() {
-                if line.contains(pat) {
-                    patfound = true;
-                    if line.ends_with(",") {
-                        finals = finals + "\n" + "    " + pat + "\"" + &value + "\","
-                    } else {
-                        finals = finals + "\n" + "    " + pat + "\"" + &value + "\""
-                    }
-                } else if line.ends_with("}") && !patfound {
-                    finals =
-                        finals + "," + "\n" + "    " + pat + "\"" + &value + "\"" + "\n" + line;
-                } else {
-                    finals = finals + "\n" + line;
-                }
-            }
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(file)
-                .expect("Couldn't open hjson file")
-                .write_all(finals.trim().as_bytes())
-                .unwrap();
-        } else {
-            finals = finals + "{\n    " + pat + "\"" + &value + "\"\n}";
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .open(file)
-                .expect("Couldn't open hjson file")
-                .write_all(finals.as_bytes())
-                .unwrap();
-        }
+        template::render(text, &self.variables()?)
     }
-    pub fn load_rofi(&self) {
-        if fs::metadata(get_home() + "/.config/rofi").is_err() {
-            fs::create_dir(get_home() + "/.config/rofi").unwrap();
+    /// Reads in the contents of one of the theme's option files, rendered with the theme's
+    /// variables if it is a template
+    pub fn read_option<N>(&self, option: N) -> Result<String, RavenError>
+    where
+        N: Into<String>,
+    {
+        let option = option.into();
+        let mut contents = String::new();
+        fs::File::open(self.option_path(option.as_str())?)?.read_to_string(&mut contents)?;
+        if self.is_template(option.as_str()) {
+            self.render(contents)
+        } else {
+            Ok(contents)
         }
-        fs::copy(
-            get_home() + "/.config/raven/themes/" + &self.name + "/rofi",
-            get_home() + "/.config/rofi/theme.rasi",
-        )
-        .expect("Couldn't copy rofi theme");
-    }
-    pub fn load_pywal(&self) {
-        let arg = get_home() + "/.config/raven/themes/" + &self.name + "/pywal";
-        Command::new("wal")
-            .arg("-n")
-            .arg("-i")
-            .arg(arg)
-            .output()
-            .expect("Couldn't run pywal");
-    }
-    pub fn load_script(&self) {
-        Command::new("sh")
-            .arg("-c")
-            .arg(get_home() + "/.config/raven/themes/" + &self.name + "/script")
-            .output()
-            .expect("Couldn't run custom script");
     }
-
-    pub fn load_openbox(&self) {
-        let mut base = String::new();
-        if fs::metadata(get_home() + "/.config/raven/base_rc.xml").is_ok() {
-            fs::File::open(get_home() + "/.config/raven/base_rc.xml")
-                .unwrap()
-                .read_to_string(&mut base)
-                .unwrap();
-        }
-        let mut rest = String::new();
-        fs::File::open(get_home() + "/.config/raven/themes/" + &self.name + "/openbox")
-            .unwrap()
-            .read_to_string(&mut rest)
-            .unwrap();
-        base.push_str(&rest);
-        fs::remove_file(get_home() + "/.config/openbox/rc.xml").unwrap();
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(get_home() + "/.config/openbox/rc.xml")
-            .expect("Couldn't open rc.xml")
-            .write_all(base.as_bytes())
-            .unwrap();
-        Command::new("openbox")
-            .arg("--reconfigure")
-            .output()
-            .expect("Couldn't reload openbox");
-    }
-    pub fn load_ranger(&self) {
-        fs::copy(
-            get_home() + "/.config/raven/themes/" + &self.name + "/ranger",
-            get_home() + "/.config/ranger/rc.conf",
-        )
-        .expect("Couldn't overwrite ranger config");
+    /// Returns the path to a usable version of one of the theme's option files. Templates are
+    /// rendered into the cache directory, other files are used as they are
+    pub fn rendered_path<N>(&self, option: N) -> Result<String, RavenError>
+    where
+        N: Into<String>,
+    {
+        let option = option.into();
+        let templated = self.is_template(option.as_str());
+        self.rendered_file(option, templated)
     }
-
-    pub fn load_dunst(&self) {
-        let mut config = String::new();
-        if fs::metadata(get_home() + "/.config/raven/base_dunst").is_ok() {
-            fs::File::open(get_home() + "/.config/raven/base_dunst")
-                .unwrap()
-                .read_to_string(&mut config)
-                .unwrap();
-        }
-        let mut app = String::new();
-        fs::File::open(get_home() + "/.config/raven/themes/" + &self.name + "/dunst")
-            .unwrap()
-            .read_to_string(&mut app)
-            .unwrap();
-        config.push_str(&app);
-        fs::remove_file(get_home() + "/.config/dunst/dunstrc").unwrap();
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(get_home() + "/.config/dunst/dunstrc")
-            .expect("Couldn't open dunstrc")
-            .write_all(config.as_bytes())
-            .unwrap();
-        Command::new("dunst").spawn().expect("Failed to run dunst");
-    }
-    pub fn load_vscode<N>(&self, value: N)
+    /// Returns the path to a usable version of a file within the theme, rendered into the cache
+    /// directory if it is a template
+    pub fn rendered_file<N>(&self, file: N, templated: bool) -> Result<String, RavenError>
     where
         N: Into<String>,
     {
-        let path1 = get_home() + "/.config/Code/User";
-        let path2 = get_home() + "/.config/Code - OSS/User";
-        if fs::metadata(&path1).is_err() && fs::metadata(&path2).is_err() {
-            println!(
-                "Couldn't find neither .config/Code nor .config/Code - OSS. Do you have VSCode installed? \
-                Skipping."
-            );
-            return;
+        let file = file.into();
+        let path = self.option_path(file.as_str())?;
+        if !templated {
+            return Ok(path);
         }
-        let pattern = "\"workbench.colorTheme\": ";
-        let value = value.into();
-        if fs::metadata(&path1).is_ok() {
-            self.edit_hjson(path1 + "/settings.json", pattern, value.as_str())
-        }
-        if fs::metadata(&path2).is_ok() {
-            self.edit_hjson(path2 + "/settings.json", pattern, value)
+        let mut contents = String::new();
+        fs::File::open(&path)?.read_to_string(&mut contents)?;
+        let dir = match self.scratch {
+            Some(ref scratch) => scratch.clone(),
+            None => self.paths.cache.clone(),
+        };
+        let rendered = dir + "/templates/" + &self.name + "/" + &file;
+        if let Some(parent) = Path::new(&rendered).parent() {
+            fs::create_dir_all(parent)?;
         }
+        fs::write(&rendered, template::render(contents, &self.variables()?)?)?;
+        fs::set_permissions(&rendered, fs::metadata(&path)?.permissions())?;
+        Ok(rendered)
     }
-    pub fn load_sublt<N>(&self, stype: N, value: N)
+    /// Returns the value of a key held within theme.json key-value storage
+    pub fn value<N>(&self, key: N) -> Result<String, RavenError>
     where
         N: Into<String>,
     {
-        let stype = &stype.into();
-        let path = get_home() + "/.config/sublime-text-3/Packages/User";
-        if fs::metadata(&path).is_err() {
-            println!(
-                "Couldn't find {}. Do you have sublime text 3 installed? \
-                 Skipping.",
-                &path
-            );
-            return;
-        }
-
-        let mut value = value.into();
-        if value.starts_with("sublt/") {
-            value = value.trim_start_matches("sublt/").to_string();
-            fs::copy(
-                get_home() + "/.config/raven/themes/" + &self.name + "/sublt/" + &value,
-                path.clone() + "/" + &value
-            )
-            .expect("Couldn't overwrite sublt theme");
+        let key = key.into();
+        match self.kv.get(&key) {
+            Some(Value::String(v)) => Ok(v.clone()),
+            Some(_) => Err(RavenError::Invalid(format!(
+                "Key {} does not hold a string",
+                key
+            ))),
+            None => Err(RavenError::MissingOption(self.name.clone(), key)),
         }
-
-        let mut pattern = "";
-        if stype == "st_tmtheme" || stype == "st_scs" {
-            pattern = "\"color_scheme\": ";
-        } else if stype == "st_subltheme" {
-            pattern = "\"theme\": ";
-        }
-        self.edit_hjson(path + "/Preferences.sublime-settings", pattern, value)
     }
-
-    pub fn load_ncm(&self) {
-        if fs::metadata(get_home() + "/.config/ncmpcpp").is_ok() {
-            fs::copy(
-                get_home() + "/.config/raven/themes/" + &self.name + "/ncmpcpp",
-                get_home() + "/.config/ncmpcpp/config",
-            )
-            .expect("Couldn't overwrite ncmpcpp config");
-        } else if fs::metadata(get_home() + "/.ncmpcpp").is_ok() {
-            fs::copy(
-                get_home() + "/.config/raven/themes/" + &self.name + "/ncmpcpp",
-                get_home() + "/.ncmpcpp/config",
-            )
-            .expect("Couldn't overwrite ncmpcpp config");
-        } else {
-            println!(
-                "Couldn't detect a ncmpcpp config directory in ~/.config/ncmppcp or ~/.ncmpcpp."
-            );
+    /// Moves an old single-string file option into key-value storage, without saving the change.
+    /// Returns the value
+    fn single_to_kv(&mut self, key: &str) -> Result<String, RavenError> {
+        let value = self.read_option(key)?.trim().to_string();
+        self.options.retain(|x| x != key);
+        self.kv
+            .insert(key.to_string(), serde_json::Value::String(value.clone()));
+        Ok(value)
+    }
+    /// Converts old single-string file options into key-value storage
+    pub fn convert_single<N>(&mut self, name: N) -> Result<(), RavenError>
+    where
+        N: Into<String>,
+    {
+        let key = name.into();
+        let value = self.single_to_kv(key.as_str())?;
+        let mut store = load_store(&self.paths, self.name.clone())?;
+        store
+            .kv
+            .insert(key.clone(), serde_json::Value::String(value));
+        store.options = store
+            .options
+            .iter()
+            .filter(|x| x.as_str() != key.as_str())
+            .map(|x| x.to_owned())
+            .collect();
+        up_theme(&self.paths, store)
+    }
+    /// Loads all options with the built-in loaders and the custom options from the config.
+    /// Returns a report on each option
+    pub fn load_all(&mut self, runner: &dyn CommandRunner) -> Result<LoadReport, RavenError> {
+        let conf = get_config(&self.paths)?;
+        self.load_with(&LoaderRegistry::with_config(&conf), runner)
+    }
+    /// Loads all options with the loaders from a registry, as one transaction. If an option
+    /// can't be loaded, the files already written are restored. Returns a report on each option
+    pub fn load_with(
+        &mut self,
+        registry: &LoaderRegistry,
+        runner: &dyn CommandRunner,
+    ) -> Result<LoadReport, RavenError> {
+        for single in SINGLE_OPTIONS.iter() {
+            if self.options.iter().any(|x| x == single) {
+                self.convert_single(*single)?;
+            }
         }
+        let mut tx = Transaction::new(&self.paths);
+        let mut report = self.stage(registry, &mut tx)?;
+        tx.commit(runner, &mut report)?;
+        Ok(report)
     }
-    pub fn load_bspwm(&self) {
-        let mut config = String::new();
-        if fs::metadata(get_home() + "/.config/raven/base_bspwm").is_ok() {
-            fs::File::open(get_home() + "/.config/raven/base_bspwm")
-                .unwrap()
-                .read_to_string(&mut config)
-                .unwrap();
+    /// Iterates through options, then key-value options, and stages the changes of the matching
+    /// loader from the registry. Nothing outside the cache is changed. Returns a report with the
+    /// options that will be applied and the ones that were skipped
+    pub fn stage(
+        &mut self,
+        registry: &LoaderRegistry,
+        tx: &mut Transaction,
+    ) -> Result<LoadReport, RavenError> {
+        for single in SINGLE_OPTIONS.iter() {
+            if self.options.iter().any(|x| x == single) {
+                self.single_to_kv(single)?;
+            }
         }
-        let mut app = String::new();
-        fs::File::open(get_home() + "/.config/raven/themes/" + &self.name + "/bspwm")
-            .unwrap()
-            .read_to_string(&mut app)
-            .unwrap();
-
-        config.push_str(&app);
-        fs::remove_file(get_home() + "/.config/bspwm/bspwmrc").unwrap();
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .mode(0o744)
-            .open(get_home() + "/.config/bspwm/bspwmrc")
-            .expect("Couldn't open bspwmrc file")
-            .write_all(config.as_bytes())
-            .unwrap();
-        Command::new("sh")
-            .arg("-c")
-            .arg(get_home() + "/.config/bspwm/bspwmrc")
-            .output()
-            .expect("Couldn't reload bspwm");
-    }
-    pub fn load_i3(&self, isw: bool) {
-        let mut config = String::new();
-        if fs::metadata(get_home() + "/.config/raven/base_i3").is_ok() {
-            fs::File::open(get_home() + "/.config/raven/base_i3")
-                .unwrap()
-                .read_to_string(&mut config)
-                .unwrap();
+        if self.options.iter().any(|x| x == "pywal")
+            && self.is_enabled("pywal")
+            && self.selection.includes("pywal")
+        {
+            // Colors set in the theme's own palette win over generated ones
+            let mut palette = extract::cached_palette(
+                &self.paths,
+                self.option_path("pywal")?,
+                self.scratch.is_none(),
+            )?;
+            palette.merge(&self.palette);
+            self.palette = palette;
         }
-        let mut app = String::new();
-        if isw {
-            fs::File::open(get_home() + "/.config/raven/themes/" + &self.name + "/wm")
-                .unwrap()
-                .read_to_string(&mut app)
-                .unwrap();
-        } else {
-            fs::File::open(get_home() + "/.config/raven/themes/" + &self.name + "/i3")
-                .unwrap()
-                .read_to_string(&mut app)
-                .unwrap();
+        let mut names = self.options.clone();
+        names.reverse();
+        // Keys without a loader are template variables rather than options
+        names.extend(
+            self.kv
+                .keys()
+                .filter(|x| registry.get(x.as_str()).is_some())
+                .cloned(),
+        );
+        let names = names
+            .into_iter()
+            .filter(|x| x != "|")
+            .collect::<Vec<String>>();
+        if let Some(missing) = self.selection.only.iter().find(|x| !names.contains(x)) {
+            return Err(RavenError::MissingOption(
+                self.name.clone(),
+                missing.clone(),
+            ));
         }
-        config.push_str(&app);
-        if fs::metadata(get_home() + "/.config/i3").is_err() {
-            fs::create_dir(get_home() + "/.config/i3").expect("Couldn't create i3 config dir");
+        let mut report = LoadReport::new(self.name.as_str());
+        for option in names {
+            if !self.selection.includes(option.as_str()) {
+                continue;
+            }
+            if !self.is_enabled(option.as_str()) {
+                report.skipped(option, "disabled");
+                continue;
+            }
+            match registry.get(option.as_str()) {
+                Some(loader) if loader.available(self) => {
+                    let start = Instant::now();
+                    tx.set_option(option.as_str());
+                    loader
+                        .apply(self, tx)
+                        .and_then(|_| loader.reload(self, tx))
+                        .map_err(|e| RavenError::LoadFailed(option.clone(), Box::new(e)))?;
+                    report.applied(option, start.elapsed());
+                }
+                Some(_) => report.skipped(option, "program not installed"),
+                None => report.skipped(option, "unknown option"),
+            }
         }
-        if fs::metadata(get_home() + "/.config/i3/config").is_ok() {
-            fs::remove_file(get_home() + "/.config/i3/config")
-                .expect("Couldn't remove previous i3 config");
+        let conf = get_config(&self.paths)?;
+        self.stage_exports(&conf.palette_exports, tx)
+            .map_err(|e| RavenError::LoadFailed("palette".to_string(), Box::new(e)))?;
+        Ok(report)
+    }
+    /// Stages writing the palette to colors.[format] in the cache directory, for each of the
+    /// given formats. Exports of other formats, or of every format if the theme has no palette,
+    /// are removed, so the previous theme's colors aren't taken for this one's
+    fn stage_exports(&self, formats: &[String], tx: &mut Transaction) -> Result<(), RavenError> {
+        tx.set_option("palette");
+        for format in EXPORT_FORMATS.iter() {
+            let target = self.paths.cache_file(String::from("colors.") + format);
+            // The pywal option writes colors.Xresources for itself
+            if (self.palette.is_empty() || !formats.iter().any(|x| x == format))
+                && !tx.writes(target.as_str())
+            {
+                tx.remove(target);
+            }
         }
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(get_home() + "/.config/i3/config")
-            .expect("Couldn't open i3 file")
-            .write_all(config.as_bytes())
-            .unwrap();
-        Command::new("i3-msg")
-            .arg("reload")
-            .output()
-            .expect("Couldn't reload i3");
-    }
-    pub fn load_termite(&self) {
-        fs::copy(
-            get_home() + "/.config/raven/themes/" + &self.name + "/termite",
-            get_home() + "/.config/termite/config",
-        )
-        .expect("Couldn't overwrite termite config");
-        Command::new("pkill")
-            .arg("-SIGUSR1")
-            .arg("termite")
-            .output()
-            .expect("Couldn't reload termite");
-    }
-    pub fn load_poly(&self, monitor: i32) {
-        for number in 0..monitor {
-            Command::new("sh")
-                .arg("-c")
-                .arg(
-                    String::from("polybar --config=")
-                        + &get_home()
-                        + "/.config/raven/themes/"
-                        + &self.name
-                        + "/poly "
-                        + &self.order[number as usize]
-                        + " > /dev/null 2> /dev/null",
-                )
-                .spawn()
-                .expect("Failed to run polybar");
+        if self.palette.is_empty() {
+            return Ok(());
         }
-    }
-    fn load_lemon(&self) {
-        Command::new("sh")
-            .arg(get_home() + "/.config/raven/themes/" + &self.name + "/lemonbar")
-            .spawn()
-            .expect("Failed to run lemonbar script");
-    }
-    fn load_wall(&self) {
-        Command::new("feh")
-            .arg("--bg-scale")
-            .arg(get_home() + "/.config/raven/themes/" + &self.name + "/wall")
-            .output()
-            .expect("Failed to change wallpaper");
-    }
-    fn load_xres(&self, merge: bool) {
-        let mut xres = Command::new("xrdb");
-        let mut name = String::from("xres");
-        if merge {
-            name.push_str("_m");
-            xres.arg("-merge");
+        let wallpaper = self
+            .option_path("wall")
+            .or_else(|_| self.option_path("pywal"))
+            .ok();
+        for format in formats {
+            let contents = self.palette.export(format.as_str(), wallpaper.as_deref())?;
+            tx.write(
+                self.paths.cache_file(String::from("colors.") + format),
+                contents,
+                0o644,
+            );
         }
-        xres.arg(get_home() + "/.config/raven/themes/" + &self.name + "/" + &name)
-            .output()
-            .expect("Could not run xrdb");
+        Ok(())
     }
 }
 
 /// Changes the theme that is currently being edited
-pub fn edit<N>(theme_name: N)
+pub fn edit<N>(paths: &RavenPaths, theme_name: N) -> Result<(), RavenError>
 where
     N: Into<String>,
 {
     let theme_name = theme_name.into();
-    if fs::metadata(get_home() + "/.config/raven/themes/" + &theme_name).is_ok() {
-        let mut conf = get_config();
+    if fs::metadata(paths.theme(theme_name.as_str())).is_ok() {
+        let mut conf = get_config(paths)?;
         conf.editing = theme_name.to_string();
-        up_config(conf);
-        println!("You are now editing the theme {}", &theme_name);
+        up_config(paths, conf)
     } else {
-        println!("That theme does not exist");
+        Err(RavenError::MissingTheme(theme_name))
     }
 }
-/// Clears possible remnants of old themes
-pub fn clear_prev() {
-    Command::new("pkill").arg("polybar").output().unwrap();
-    Command::new("pkill").arg("lemonbar").output().unwrap();
-    Command::new("pkill").arg("dunst").output().unwrap();
+/// Stages stopping the processes previously loaded themes started, for the options in the
+/// config's stop list that are selected. Returns the recorded processes that are left running
+fn stage_stop(
+    paths: &RavenPaths,
+    conf: &Config,
+    selection: &Selection,
+    tx: &mut Transaction,
+) -> Result<Vec<Spawned>, RavenError> {
+    match load_spawned(paths)? {
+        Some(spawned) => {
+            let (stop, keep): (Vec<Spawned>, Vec<Spawned>) = spawned.into_iter().partition(|x| {
+                conf.stop.contains(&x.option) && selection.includes(x.option.as_str())
+            });
+            // Processes that have exited are dropped without signalling, as their PIDs may now
+            // belong to something else
+            for process in stop.into_iter().filter(Spawned::alive) {
+                tx.set_option(process.option.as_str());
+                tx.run(process.stop_command());
+            }
+            Ok(keep)
+        }
+        None => {
+            // Raven has never recorded anything, as before it tracked processes, so fall back to
+            // stopping the programs by name. Once it has, this isn't done again
+            let names = conf
+                .stop
+                .iter()
+                .filter(|x| selection.includes(x.as_str()))
+                .cloned()
+                .collect::<Vec<String>>();
+            LoaderRegistry::builtin().cleanup(paths, &names, tx)?;
+            Ok(Vec::new())
+        }
+    }
+}
+/// Stops the processes started by previously loaded themes, for the options in the config's stop
+/// list that are selected
+pub fn clear_prev(
+    paths: &RavenPaths,
+    selection: &Selection,
+    runner: &dyn CommandRunner,
+) -> Result<(), RavenError> {
+    let conf = get_config(paths)?;
+    let mut tx = Transaction::new(paths);
+    let keep = stage_stop(paths, &conf, selection, &mut tx)?;
+    save_spawned(paths, &keep)?;
+    tx.run_commands(runner)
 }
 /// Deletes theme from registry
-pub fn del_theme<N>(theme_name: N)
+pub fn del_theme<N>(paths: &RavenPaths, theme_name: N) -> Result<(), RavenError>
 where
     N: Into<String>,
 {
-    fs::remove_dir_all(get_home() + "/.config/raven/themes/" + &theme_name.into())
-        .expect("Couldn't delete theme");;
+    let theme_name = theme_name.into();
+    if fs::metadata(paths.theme(theme_name.as_str())).is_err() {
+        return Err(RavenError::MissingTheme(theme_name));
+    }
+    fs::remove_dir_all(paths.theme(theme_name.as_str()))?;
+    Ok(())
 }
-/// Loads last loaded theme from string of last theme's name
-pub fn refresh_theme<N>(last: N)
+/// Loads the selected options of the last loaded theme, from string of last theme's name, with
+/// the variant it was loaded with. Returns a report on each option
+pub fn refresh_theme<N>(
+    paths: &RavenPaths,
+    last: N,
+    variant: Option<String>,
+    selection: Selection,
+    runner: &dyn CommandRunner,
+) -> Result<LoadReport, RavenError>
 where
     N: Into<String>,
 {
     let last = last.into();
     if last.chars().count() > 0 {
-        run_theme(load_theme(last.trim()).unwrap());
+        let mut theme = load_theme(paths, last.trim())?.with_selection(selection);
+        if let Some(variant) = variant {
+            theme = theme.with_variant(variant)?;
+        }
+        run_theme(theme, runner)
     } else {
-        println!("No last theme saved. Cannot refresh.");
+        Err(RavenError::Invalid(
+            "No last theme saved. Cannot refresh.".to_string(),
+        ))
     }
 }
 /// Create new theme directory and 'theme' file
-pub fn new_theme<N>(theme_name: N)
+pub fn new_theme<N>(paths: &RavenPaths, theme_name: N) -> Result<(), RavenError>
 where
     N: Into<String>,
 {
     let theme_name = theme_name.into();
-    let res = fs::create_dir(get_home() + "/.config/raven/themes/" + &theme_name);
-    if res.is_ok() {
-        res.unwrap();
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(get_home() + "/.config/raven/themes/" + &theme_name + "/theme.json")
-            .expect("can open");
-        let stdef = ThemeStore {
-            name: theme_name.clone(),
-            options: vec![],
-            enabled: vec![],
-            screenshot: default_screen(),
-            description: default_desc(),
-            kv: Map::new(),
-        };
-        let st = serde_json::to_string(&stdef).unwrap();
-        file.write_all(st.as_bytes()).unwrap();
-        edit(theme_name);
-    } else {
-        println!("Theme {} already exists", &theme_name);
+    if fs::create_dir(paths.theme(theme_name.as_str())).is_err() {
+        return Err(RavenError::ThemeExists(theme_name));
     }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(paths.theme_file(theme_name.as_str(), "theme.json"))?;
+    let stdef = ThemeStore {
+        name: theme_name.clone(),
+        extends: None,
+        variants: BTreeMap::new(),
+        tags: vec![],
+        templates: vec![],
+        options: vec![],
+        enabled: vec![],
+        disabled: vec![],
+        screenshot: default_screen(),
+        description: default_desc(),
+        kv: Map::new(),
+        palette: Palette::default(),
+    };
+    let st = serde_json::to_string(&stdef)?;
+    file.write_all(st.as_bytes())?;
+    edit(paths, theme_name)
+}
+/// Makes a theme build on another one, inheriting the options, key-value options and palette
+/// colors it doesn't set itself
+pub fn set_extends<N, S>(paths: &RavenPaths, theme_name: N, parent: S) -> Result<(), RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let (theme_name, parent) = (theme_name.into(), parent.into());
+    let mut store = load_store(paths, theme_name.as_str())?;
+    let previous = store.extends.replace(parent);
+    up_theme(paths, store)?;
+    // Put the previous parent back if the new one is missing or leads back to this theme
+    if let Err(e) = resolve_store(paths, theme_name.as_str()) {
+        let mut store = load_store(paths, theme_name.as_str())?;
+        store.extends = previous;
+        up_theme(paths, store)?;
+        return Err(e);
+    }
+    Ok(())
 }
 /// Add an option to a theme
-pub fn add_to_theme<N>(theme_name: N, option: N, path: N)
+pub fn add_to_theme<N>(
+    paths: &RavenPaths,
+    theme_name: N,
+    option: N,
+    path: N,
+) -> Result<(), RavenError>
 where
     N: Into<String>,
 {
     let (theme_name, option, path) = (theme_name.into(), option.into(), path.into());
-    let cur_theme = load_theme(theme_name.as_str()).unwrap();
-    let cur_st = load_store(theme_name.as_str());
-    let mut new_themes = ThemeStore {
-        name: theme_name.clone(),
-        options: cur_theme.options,
-        enabled: cur_theme.enabled,
-        screenshot: cur_st.screenshot,
-        description: cur_st.description,
-        kv: Map::new(),
-    };
-    let mut already_used = false;
-    for opt in &new_themes.options {
-        if opt == &option {
-            already_used = true;
-        }
-    }
-    if !already_used {
-        new_themes.options.push(option.clone());
-        up_theme(new_themes);
-    }
-    let mut totpath = env::current_dir().unwrap();
+    let mut store = load_store(paths, theme_name.as_str())?;
+    let mut totpath = env::current_dir()?;
     totpath.push(path);
     fs::copy(
         totpath,
-        get_home() + "/.config/raven/themes/" + &theme_name + "/" + &option,
-    )
-    .expect("Couldn't copy config in");
+        paths.theme_file(theme_name.as_str(), option.as_str()),
+    )?;
+    if !store.options.contains(&option) {
+        store.options.push(option);
+        up_theme(paths, store)?;
+    }
+    Ok(())
+}
+/// Add an option file to one of a theme's variants, creating the variant if needed
+pub fn add_to_variant<N, S, T, U>(
+    paths: &RavenPaths,
+    theme_name: N,
+    variant: S,
+    option: T,
+    path: U,
+) -> Result<(), RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+    T: Into<String>,
+    U: Into<String>,
+{
+    let (theme_name, variant, option) = (theme_name.into(), variant.into(), option.into());
+    let mut store = load_store(paths, theme_name.as_str())?;
+    let mut totpath = env::current_dir()?;
+    totpath.push(path.into());
+    let target = paths.variant_file(theme_name.as_str(), variant.as_str(), option.as_str());
+    if let Some(parent) = Path::new(&target).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(totpath, target)?;
+    let overrides = store
+        .variants
+        .entry(variant)
+        .or_insert_with(Variant::default);
+    if !overrides.options.contains(&option) {
+        overrides.options.push(option);
+    }
+    up_theme(paths, store)
+}
+/// Changes a key-value option in one of a theme's variants, creating the variant if needed
+pub fn variant_key_value<N, S, T, U>(
+    paths: &RavenPaths,
+    theme_name: N,
+    variant: S,
+    key: T,
+    value: U,
+) -> Result<(), RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+    T: Into<String>,
+    U: Into<String>,
+{
+    let mut store = load_store(paths, theme_name.into())?;
+    store
+        .variants
+        .entry(variant.into())
+        .or_insert_with(Variant::default)
+        .kv
+        .insert(key.into(), Value::String(value.into()));
+    up_theme(paths, store)
+}
+/// Loads the last loaded theme with its next variant, ready to run. Goes from the theme without
+/// a variant through each of its variants in order, and back
+pub fn toggle_variant(paths: &RavenPaths) -> Result<Theme, RavenError> {
+    let conf = get_config(paths)?;
+    let last = conf.last.trim();
+    if last.is_empty() {
+        return Err(RavenError::Invalid(
+            "No last theme saved. Cannot toggle its variant.".to_string(),
+        ));
+    }
+    let theme = load_theme(paths, last)?;
+    if theme.variants.is_empty() {
+        return Err(RavenError::Invalid(format!(
+            "Theme {} has no variants",
+            last
+        )));
+    }
+    match load_store(paths, last)?.next_variant(conf.last_variant()) {
+        Some(variant) => theme.with_variant(variant),
+        None => Ok(theme),
+    }
 }
 /// Remove an option from a theme
-pub fn rm_from_theme<N>(theme_name: N, option: N)
+pub fn rm_from_theme<N>(paths: &RavenPaths, theme_name: N, option: N) -> Result<(), RavenError>
 where
     N: Into<String>,
 {
     let (theme_name, option) = (theme_name.into(), option.into());
-    let cur_theme = load_theme(theme_name.as_str()).unwrap();
-    let cur_st = load_store(theme_name.as_str());
-    let mut new_themes = ThemeStore {
-        name: theme_name,
-        options: cur_theme.options,
-        enabled: cur_theme.enabled,
-        screenshot: cur_st.screenshot,
-        description: cur_st.description,
-        kv: Map::new(),
-    };
-    let mut found = false;
-    let mut i = 0;
-    while i < new_themes.options.len() {
-        if &new_themes.options[i] == &option {
-            println!("Found option {}", option);
-            found = true;
-            new_themes.options.remove(i);
+    let mut store = load_store(paths, theme_name.as_str())?;
+    if !store.options.contains(&option) {
+        return Err(RavenError::MissingOption(theme_name, option));
+    }
+    store.options.retain(|x| x != &option);
+    store.disabled.retain(|x| x != &option);
+    up_theme(paths, store)
+}
+/// Makes an option of a theme load with it again. Returns false if it already did
+pub fn enable_option<N, S>(paths: &RavenPaths, theme_name: N, option: S) -> Result<bool, RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let (theme_name, option) = (theme_name.into(), option.into());
+    let mut store = load_store(paths, theme_name.as_str())?;
+    let names = resolve_store(paths, theme_name.as_str())?.0.option_names();
+    if !names.contains(&option) {
+        return Err(RavenError::MissingOption(theme_name, option));
+    }
+    if store.is_enabled(option.as_str()) {
+        return Ok(false);
+    }
+    store.disabled.retain(|x| *x != option);
+    up_theme(paths, store)?;
+    Ok(true)
+}
+/// Stops an option of a theme from loading, without removing it. Returns false if it already
+/// didn't load
+pub fn disable_option<N, S>(
+    paths: &RavenPaths,
+    theme_name: N,
+    option: S,
+) -> Result<bool, RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let (theme_name, option) = (theme_name.into(), option.into());
+    let mut store = load_store(paths, theme_name.as_str())?;
+    let names = resolve_store(paths, theme_name.as_str())?.0.option_names();
+    if !names.contains(&option) {
+        return Err(RavenError::MissingOption(theme_name, option));
+    }
+    if !store.is_enabled(option.as_str()) {
+        return Ok(false);
+    }
+    store.disabled.push(option);
+    up_theme(paths, store)?;
+    Ok(true)
+}
+/// Adds a tag to a theme. Returns false if it already had it
+pub fn tag_theme<N, S>(paths: &RavenPaths, theme_name: N, tag: S) -> Result<bool, RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let tag = tag.into();
+    let mut store = load_store(paths, theme_name)?;
+    if store.tags.contains(&tag) {
+        return Ok(false);
+    }
+    store.tags.push(tag);
+    up_theme(paths, store)?;
+    Ok(true)
+}
+/// Removes a tag from a theme. Returns false if it didn't have it
+pub fn untag_theme<N, S>(paths: &RavenPaths, theme_name: N, tag: S) -> Result<bool, RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let tag = tag.into();
+    let mut store = load_store(paths, theme_name)?;
+    if !store.tags.contains(&tag) {
+        return Ok(false);
+    }
+    store.tags.retain(|x| *x != tag);
+    up_theme(paths, store)?;
+    Ok(true)
+}
+/// Marks an option of a theme as a template, rendered with the theme's variables when loading.
+/// Returns false if it already was one
+pub fn template_option<N, S>(
+    paths: &RavenPaths,
+    theme_name: N,
+    option: S,
+) -> Result<bool, RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let (theme_name, option) = (theme_name.into(), option.into());
+    let mut store = load_store(paths, theme_name.as_str())?;
+    let names = resolve_store(paths, theme_name.as_str())?.0.option_names();
+    if !names.contains(&option) {
+        return Err(RavenError::MissingOption(theme_name, option));
+    }
+    if store.templates.contains(&option) {
+        return Ok(false);
+    }
+    store.templates.push(option);
+    up_theme(paths, store)?;
+    Ok(true)
+}
+/// Stops rendering an option of a theme as a template, so its file is used as it is. Returns
+/// false if it wasn't one
+pub fn untemplate_option<N, S>(
+    paths: &RavenPaths,
+    theme_name: N,
+    option: S,
+) -> Result<bool, RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let option = option.into();
+    let mut store = load_store(paths, theme_name)?;
+    if !store.templates.contains(&option) {
+        return Ok(false);
+    }
+    store.templates.retain(|x| *x != option);
+    up_theme(paths, store)?;
+    Ok(true)
+}
+/// Creates a new theme out of options of existing themes. Option files are copied, and
+/// key-value options carried over. Nothing is created if one of the options doesn't exist
+pub fn compose_theme<N>(paths: &RavenPaths, theme_name: N, parts: &[Part]) -> Result<(), RavenError>
+where
+    N: Into<String>,
+{
+    let theme_name = theme_name.into();
+    if fs::metadata(paths.theme(theme_name.as_str())).is_ok() {
+        return Err(RavenError::ThemeExists(theme_name));
+    }
+    let mut files: Vec<(String, String)> = Vec::new();
+    let mut kv = Map::new();
+    let mut templates = Vec::new();
+    let mut taken: Vec<(&str, &str)> = Vec::new();
+    for part in parts {
+        let (store, origins) = resolve_store(paths, part.theme.as_str())?;
+        for option in &part.options {
+            if let Some((_, from)) = taken.iter().find(|(x, _)| x == option) {
+                return Err(RavenError::Invalid(format!(
+                    "{} is taken from both {} and {}",
+                    option, from, part.theme
+                )));
+            }
+            taken.push((option.as_str(), part.theme.as_str()));
+            if store.templates.contains(option) {
+                templates.push(option.clone());
+            }
+            if store.options.contains(option) {
+                files.push((
+                    option.clone(),
+                    paths.theme_file(origins[option].as_str(), option.as_str()),
+                ));
+            } else if let Some(value) = store.kv.get(option) {
+                kv.insert(option.clone(), value.clone());
+            } else {
+                return Err(RavenError::MissingOption(
+                    part.theme.clone(),
+                    option.clone(),
+                ));
+            }
         }
-        i += 1;
     }
-    if found {
-        up_theme(new_themes);
-    } else {
-        println!("Couldn't find option {}", option);
+    new_theme(paths, theme_name.as_str())?;
+    let res = files
+        .into_iter()
+        .try_for_each(|(option, source)| add_to_theme(paths, theme_name.clone(), option, source))
+        .and_then(|_| {
+            let mut store = load_store(paths, theme_name.as_str())?;
+            store.kv = kv;
+            store.templates = templates;
+            up_theme(paths, store)
+        });
+    if res.is_err() {
+        let _ = del_theme(paths, theme_name.as_str());
     }
+    res
+}
+/// Run/refresh a loaded Theme. Returns a report on each option
+pub fn run_theme(new_theme: Theme, runner: &dyn CommandRunner) -> Result<LoadReport, RavenError> {
+    let conf = get_config(&new_theme.paths)?;
+    run_theme_with(new_theme, &LoaderRegistry::with_config(&conf), runner)
 }
-/// Run/refresh a loaded Theme
-pub fn run_theme(new_theme: Theme) {
-    new_theme.load_all();
+/// Run/refresh a loaded Theme with the loaders from a registry. Returns a report on each option
+pub fn run_theme_with(
+    mut new_theme: Theme,
+    registry: &LoaderRegistry,
+    runner: &dyn CommandRunner,
+) -> Result<LoadReport, RavenError> {
+    let mut conf = get_config(&new_theme.paths)?;
+    let report = match new_theme.load_with(registry, runner) {
+        Ok(report) => report,
+        Err(e) => {
+            // The previous theme's files are back in place, so restart what it runs. This is
+            // best effort, since the original error is what gets reported
+            if !conf.last.trim().is_empty() {
+                let _ = reload_theme(
+                    &new_theme.paths,
+                    conf.last.trim(),
+                    conf.last_variant(),
+                    &new_theme.selection,
+                    registry,
+                    runner,
+                );
+            }
+            return Err(e);
+        }
+    };
     // Updates the 'last loaded theme' information for later use by raven refresh
-    let mut conf = get_config();
     conf.last = new_theme.name;
-    up_config(conf);
+    conf.last_variant = new_theme.variant.unwrap_or_default();
+    up_config(&new_theme.paths, conf)?;
+    Ok(report)
+}
+/// Stages everything loading a theme would do, including stopping the previous theme's
+/// programs, without changing anything. Returns the transaction and a report on which options
+/// would be applied or skipped
+pub fn plan_theme(theme: &mut Theme) -> Result<(Transaction, LoadReport), RavenError> {
+    let conf = get_config(&theme.paths)?;
+    let mut tx = Transaction::new(&theme.paths);
+    stage_stop(&theme.paths, &conf, &theme.selection, &mut tx)?;
+    // Templates are rendered into a throwaway directory. Copies have already read them by the
+    // time it is removed
+    let scratch = env::temp_dir()
+        .join(format!("raven-plan-{}", process::id()))
+        .to_string_lossy()
+        .into_owned();
+    theme.scratch = Some(scratch.clone());
+    let report = theme.stage(&LoaderRegistry::with_config(&conf), &mut tx);
+    theme.scratch = None;
+    let _ = fs::remove_dir_all(&scratch);
+    Ok((tx, report?))
+}
+/// Re-runs the reload commands of the selected options of a theme whose files are already in
+/// place
+fn reload_theme<N>(
+    paths: &RavenPaths,
+    name: N,
+    variant: Option<&str>,
+    selection: &Selection,
+    registry: &LoaderRegistry,
+    runner: &dyn CommandRunner,
+) -> Result<(), RavenError>
+where
+    N: Into<String>,
+{
+    let mut theme = load_theme(paths, name)?.with_selection(selection.clone());
+    if let Some(variant) = variant {
+        theme = theme.with_variant(variant)?;
+    }
+    let mut tx = Transaction::new(paths);
+    theme.stage(registry, &mut tx)?;
+    tx.run_commands(runner)
 }
 /// Get all themes
-pub fn get_themes() -> Vec<String> {
-    fs::read_dir(get_home() + "/.config/raven/themes")
-        .expect("Couldn't read themes")
-        .collect::<Vec<io::Result<DirEntry>>>()
+pub fn get_themes(paths: &RavenPaths) -> Result<Vec<String>, RavenError> {
+    Ok(fs::read_dir(paths.themes())?
+        .collect::<io::Result<Vec<DirEntry>>>()?
         .into_iter()
-        .map(|x| proc_path(x.unwrap()))
-        .collect::<Vec<String>>()
+        .map(proc_path)
+        .collect::<Vec<String>>())
 }
 /// Changes a key-value option
-pub fn key_value<N, S, T>(key: N, value: S, theme: T) where N : Into<String>, S: Into<String>, T: Into<String> {
-    let mut store = load_store(theme.into());
-    store.kv.insert(key.into(), serde_json::Value::String(value.into()));
-    up_theme(store);
+pub fn key_value<N, S, T>(paths: &RavenPaths, key: N, value: S, theme: T) -> Result<(), RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+    T: Into<String>,
+{
+    let key = key.into();
+    let mut store = load_store(paths, theme.into())?;
+    store
+        .kv
+        .insert(key, serde_json::Value::String(value.into()));
+    up_theme(paths, store)
+}
+/// Sets a color in a theme's palette
+pub fn palette_set<N, S, T>(
+    paths: &RavenPaths,
+    theme: N,
+    key: S,
+    value: T,
+) -> Result<(), RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+    T: Into<String>,
+{
+    let mut store = load_store(paths, theme.into())?;
+    store.palette.set(key, value)?;
+    up_theme(paths, store)
+}
+/// Imports colors from a pywal colors.json or an Xresources file into a theme's palette. Returns
+/// the imported palette
+pub fn palette_import<N, S>(paths: &RavenPaths, theme: N, file: S) -> Result<Palette, RavenError>
+where
+    N: Into<String>,
+    S: Into<String>,
+{
+    let imported = Palette::import(file)?;
+    if imported.is_empty() {
+        return Err(RavenError::Invalid(
+            "Couldn't find any colors to import".to_string(),
+        ));
+    }
+    let mut store = load_store(paths, theme.into())?;
+    store.palette.merge(&imported);
+    up_theme(paths, store)?;
+    Ok(imported)
 }
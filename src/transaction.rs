@@ -0,0 +1,416 @@
+use backup::save_original;
+use error::RavenError;
+use paths::RavenPaths;
+use procs::{record_spawned, Spawned};
+use report::{LoadReport, Status};
+use runner::{Cmd, CommandRunner};
+use std::{
+    fs,
+    fs::OpenOptions,
+    io,
+    io::Write,
+    os::unix::fs::{symlink, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+/// A change to the desktop staged by an option loader
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// Write a file, replacing whatever is there
+    Write {
+        target: String,
+        contents: Vec<u8>,
+        mode: u32,
+    },
+    /// Replace a file with a symlink
+    Symlink { target: String, source: String },
+    /// Remove a file, if it exists
+    Remove { target: String },
+    /// Run a command, once all files are in place
+    Run(Cmd),
+}
+/// A staged change, with the option that staged it
+#[derive(Clone, Debug)]
+pub struct Action {
+    pub option: String,
+    pub change: Change,
+}
+/// What a target was before a transaction replaced it
+enum Prior {
+    File(Vec<u8>, u32),
+    Link(PathBuf),
+    Missing,
+}
+impl Prior {
+    fn read(target: &str) -> io::Result<Prior> {
+        match fs::symlink_metadata(target) {
+            Ok(ref meta) if meta.file_type().is_symlink() => {
+                Ok(Prior::Link(fs::read_link(target)?))
+            }
+            Ok(meta) => Ok(Prior::File(fs::read(target)?, meta.permissions().mode())),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Prior::Missing),
+            Err(e) => Err(e),
+        }
+    }
+    fn restore(&self, target: &str) -> io::Result<()> {
+        match self {
+            Prior::File(contents, mode) => write_atomic(target, contents, *mode),
+            Prior::Link(source) => link_atomic(target, source),
+            Prior::Missing => match fs::remove_file(target) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                res => res,
+            },
+        }
+    }
+}
+/// Path of the temporary file a target is written to before being moved into place
+fn temp_path(target: &str) -> String {
+    let path = Path::new(target);
+    let name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(String::from(".") + &name + ".raven~")
+        .to_string_lossy()
+        .into_owned()
+}
+/// Writes a file by writing a temporary file next to it and renaming it over the target, so the
+/// target is never left half-written
+fn write_atomic(target: &str, contents: &[u8], mode: u32) -> io::Result<()> {
+    if let Some(parent) = Path::new(target).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = temp_path(target);
+    let _ = fs::remove_file(&tmp);
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(mode)
+        .open(&tmp)?
+        .write_all(contents)?;
+    fs::rename(tmp, target)
+}
+/// Replaces a file with a symlink, the same way as `write_atomic`
+fn link_atomic<P>(target: &str, source: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    if let Some(parent) = Path::new(target).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = temp_path(target);
+    let _ = fs::remove_file(&tmp);
+    symlink(source, &tmp)?;
+    fs::rename(tmp, target)
+}
+/// Collects the changes the options of a theme make, so they can be applied together and undone
+/// if one of them fails
+pub struct Transaction {
+    paths: RavenPaths,
+    option: String,
+    actions: Vec<Action>,
+}
+impl Transaction {
+    pub fn new(paths: &RavenPaths) -> Transaction {
+        Transaction {
+            paths: paths.clone(),
+            option: String::new(),
+            actions: Vec::new(),
+        }
+    }
+    /// Sets the option that the following changes are staged for
+    pub fn set_option<N>(&mut self, option: N)
+    where
+        N: Into<String>,
+    {
+        self.option = option.into();
+    }
+    /// All staged changes, in order
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+    fn push(&mut self, change: Change) {
+        self.actions.push(Action {
+            option: self.option.clone(),
+            change,
+        });
+    }
+    /// Stages writing a file
+    pub fn write<N, C>(&mut self, target: N, contents: C, mode: u32)
+    where
+        N: Into<String>,
+        C: Into<Vec<u8>>,
+    {
+        self.push(Change::Write {
+            target: target.into(),
+            contents: contents.into(),
+            mode,
+        });
+    }
+    /// Stages copying a file over a target. The source is read right away
+    pub fn copy<N, S>(&mut self, source: N, target: S) -> Result<(), RavenError>
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        let source = source.into();
+        let mode = fs::metadata(&source)?.permissions().mode();
+        self.write(target, fs::read(&source)?, mode);
+        Ok(())
+    }
+    /// Stages replacing a target with a symlink
+    pub fn symlink<N, S>(&mut self, source: N, target: S)
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        self.push(Change::Symlink {
+            target: target.into(),
+            source: source.into(),
+        });
+    }
+    /// Stages removing a file
+    pub fn remove<N>(&mut self, target: N)
+    where
+        N: Into<String>,
+    {
+        self.push(Change::Remove {
+            target: target.into(),
+        });
+    }
+    /// Stages running a command
+    pub fn run(&mut self, cmd: Cmd) {
+        self.push(Change::Run(cmd));
+    }
+    /// Whether writing a file is already staged
+    pub fn writes(&self, target: &str) -> bool {
+        self.actions.iter().any(|x| match x.change {
+            Change::Write { target: ref t, .. } => t == target,
+            _ => false,
+        })
+    }
+    /// The contents a file will have once the transaction is committed: the last staged write to
+    /// it, or what is on disk. None if it doesn't exist or is staged to be removed
+    pub fn contents<N>(&self, target: N) -> Result<Option<String>, RavenError>
+    where
+        N: Into<String>,
+    {
+        let target = target.into();
+        for action in self.actions.iter().rev() {
+            match action.change {
+                Change::Write {
+                    target: ref t,
+                    ref contents,
+                    ..
+                } if *t == target => {
+                    return Ok(Some(String::from_utf8_lossy(contents).into_owned()));
+                }
+                Change::Remove { target: ref t } if *t == target => return Ok(None),
+                _ => {}
+            }
+        }
+        match fs::read_to_string(&target) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+    /// Writes all staged files, then runs the staged commands. Background processes are recorded
+    /// so the next load can stop them, and the time taken and commands exiting unsuccessfully are
+    /// added to the report. If a file can't be written or a command can't be started, the files
+    /// written so far are put back the way they were, the processes started so far are stopped,
+    /// and the error names the failing option
+    pub fn commit(
+        &self,
+        runner: &dyn CommandRunner,
+        report: &mut LoadReport,
+    ) -> Result<(), RavenError> {
+        let mut written: Vec<(String, Prior)> = Vec::new();
+        for action in &self.actions {
+            let start = Instant::now();
+            if let Err(e) = self.apply(action, &mut written) {
+                self.roll_back(written);
+                return Err(RavenError::LoadFailed(action.option.clone(), Box::new(e)));
+            }
+            report.add_time(&action.option, start.elapsed());
+        }
+        let mut spawned = Vec::new();
+        if let Err((option, e)) = self.run_all(runner, &mut spawned, Some(report)) {
+            self.roll_back(written);
+            for process in spawned {
+                let _ = runner.output(&process.stop_command());
+            }
+            return Err(RavenError::LoadFailed(option, Box::new(e)));
+        }
+        record_spawned(&self.paths, &spawned)
+    }
+    /// Runs only the staged commands, for restarting a theme whose files are already in place
+    pub fn run_commands(&self, runner: &dyn CommandRunner) -> Result<(), RavenError> {
+        let mut spawned = Vec::new();
+        let res = self.run_all(runner, &mut spawned, None).map_err(|(_, e)| e);
+        record_spawned(&self.paths, &spawned)?;
+        res
+    }
+    /// Runs the staged commands in order, collecting the background processes started. If there
+    /// is a report, timings and unsuccessful exits are added to it. Stops at the first command
+    /// that can't be started, returning its option
+    fn run_all(
+        &self,
+        runner: &dyn CommandRunner,
+        spawned: &mut Vec<Spawned>,
+        mut report: Option<&mut LoadReport>,
+    ) -> Result<(), (String, RavenError)> {
+        for action in &self.actions {
+            let cmd = match action.change {
+                Change::Run(ref cmd) => cmd,
+                _ => continue,
+            };
+            let start = Instant::now();
+            let fail = |e| (action.option.clone(), e);
+            if cmd.background {
+                spawned.push(Spawned::new(
+                    action.option.as_str(),
+                    runner.spawn(cmd).map_err(fail)?,
+                    cmd.to_string(),
+                ));
+            } else {
+                let out = runner.output(cmd).map_err(fail)?;
+                if let Some(ref mut report) = report {
+                    if cmd.check && !out.success() {
+                        report.fail(
+                            &action.option,
+                            Status::Failed {
+                                command: cmd.to_string(),
+                                code: out.code,
+                                stderr: out.stderr.trim().to_string(),
+                            },
+                        );
+                    }
+                }
+            }
+            if let Some(ref mut report) = report {
+                report.add_time(&action.option, start.elapsed());
+            }
+        }
+        Ok(())
+    }
+    fn apply(&self, action: &Action, written: &mut Vec<(String, Prior)>) -> Result<(), RavenError> {
+        let target = match action.change {
+            Change::Write { ref target, .. }
+            | Change::Symlink { ref target, .. }
+            | Change::Remove { ref target } => target,
+            Change::Run(_) => return Ok(()),
+        };
+        if !written.iter().any(|(t, _)| t == target) {
+            if !self.owned(target) {
+                save_original(&self.paths, target.as_str())?;
+            }
+            written.push((target.clone(), Prior::read(target)?));
+        }
+        match action.change {
+            Change::Write {
+                ref contents, mode, ..
+            } => write_atomic(target, contents, mode)?,
+            Change::Symlink { ref source, .. } => link_atomic(target, source)?,
+            Change::Remove { .. } => Prior::Missing.restore(target)?,
+            Change::Run(_) => {}
+        }
+        Ok(())
+    }
+    /// Whether a target is one of raven's own files, which aren't backed up
+    fn owned(&self, target: &str) -> bool {
+        target.starts_with(&(self.paths.cache.clone() + "/"))
+            || target.starts_with(&(self.paths.root.clone() + "/"))
+    }
+    /// Puts back the files a failed commit replaced, newest first. Errors are ignored so that as
+    /// much as possible is restored
+    fn roll_back(&self, written: Vec<(String, Prior)>) {
+        for (target, prior) in written.into_iter().rev() {
+            let _ = prior.restore(&target);
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{get_config, init, load_theme, up_config, CustomMode, CustomOption};
+    use loaders::LoaderRegistry;
+    use runner::RecordingRunner;
+    use themes::{add_to_theme, new_theme, Theme};
+    /// Creates a theme named test with the given option files, and loads it in
+    fn test_theme(paths: &RavenPaths, options: &[(&str, &str)]) -> Theme {
+        init(paths).unwrap();
+        new_theme(paths, "test").unwrap();
+        for (option, contents) in options {
+            let source = paths.home.clone() + "/" + option;
+            fs::write(&source, contents).unwrap();
+            add_to_theme(paths, "test", option, source.as_str()).unwrap();
+        }
+        load_theme(paths, "test").unwrap()
+    }
+    fn registry(paths: &RavenPaths) -> LoaderRegistry {
+        LoaderRegistry::with_config(&get_config(paths).unwrap())
+    }
+    #[test]
+    fn load_writes_files_and_runs_commands() {
+        let paths = RavenPaths::temp("tx-load");
+        let mut theme = test_theme(
+            &paths,
+            &[
+                ("i3", "bindsym $mod+Return exec st\n"),
+                ("xres", "*color0: #000000\n"),
+            ],
+        );
+        let runner = RecordingRunner::new();
+        theme.load_with(&registry(&paths), &runner).unwrap();
+        assert_eq!(
+            fs::read_to_string(paths.target("i3/config")).unwrap(),
+            "bindsym $mod+Return exec st\n"
+        );
+        assert_eq!(
+            runner.commands(),
+            vec![
+                Cmd::new("xrdb").arg(paths.theme_file("test", "xres")),
+                Cmd::new("i3-msg").arg("reload"),
+            ]
+        );
+    }
+    #[test]
+    fn failed_command_rolls_back_writes_and_symlinks() {
+        let paths = RavenPaths::temp("tx-rollback");
+        fs::create_dir_all(paths.target("i3")).unwrap();
+        fs::write(paths.target("i3/config"), "old config\n").unwrap();
+        let mut theme = test_theme(
+            &paths,
+            &[
+                ("i3", "new config\n"),
+                ("app", "app config\n"),
+                ("xres", ""),
+            ],
+        );
+        let mut conf = get_config(&paths).unwrap();
+        conf.custom.push(CustomOption {
+            name: "app".to_string(),
+            path: "~/.config/app/config".to_string(),
+            mode: CustomMode::Symlink,
+            reload: None,
+        });
+        up_config(&paths, conf).unwrap();
+        let runner = RecordingRunner::new().with_missing("xrdb");
+        match theme.load_with(&registry(&paths), &runner) {
+            Err(RavenError::LoadFailed(ref option, _)) => assert_eq!(option, "xres"),
+            res => panic!("expected xres to fail, got {:?}", res.map(|_| ())),
+        }
+        assert_eq!(
+            fs::read_to_string(paths.target("i3/config")).unwrap(),
+            "old config\n"
+        );
+        assert!(fs::symlink_metadata(paths.target("app/config")).is_err());
+        // Nothing after the failing command runs
+        assert_eq!(
+            runner.commands(),
+            vec![Cmd::new("xrdb").arg(paths.theme_file("test", "xres"))]
+        );
+    }
+}